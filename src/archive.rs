@@ -0,0 +1,128 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fmt::Display,
+    str::FromStr,
+};
+
+use crate::object::Object;
+
+// objects bundled with an index of the labels they declare, so that only the members needed
+// are linked, see `link_with`
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Debug, Clone, Default)]
+pub struct Archive {
+    // member declaring each global label, the first one when many do
+    #[serde(default)]
+    pub index: BTreeMap<String, usize>,
+    #[serde(default)]
+    pub members: Vec<Object>,
+}
+
+impl Archive {
+    pub fn new(members: Vec<Object>) -> Self {
+        let mut index = BTreeMap::new();
+
+        for (member_index, member) in members.iter().enumerate() {
+            for name in member.globals() {
+                index.entry(name.to_owned()).or_insert(member_index);
+            }
+        }
+
+        Self { index, members }
+    }
+}
+
+// members declaring the labels the objects reference without declaring, and the ones those
+// members reference in turn, in archive order, labels no member declares are left for the
+// linker to report
+pub fn resolve<'a>(objects: &'a [Object], archives: &'a [Archive]) -> Vec<&'a Object> {
+    let mut declared: HashSet<_> = objects.iter().flat_map(Object::globals).collect();
+    let mut pending: Vec<_> = objects.iter().flat_map(Object::externs).collect();
+    // archive and member index of the members pulled in
+    let mut pulled = BTreeSet::new();
+
+    while let Some(name) = pending.pop() {
+        if declared.contains(name) {
+            continue;
+        }
+
+        let Some((archive, member)) = archives
+            .iter()
+            .enumerate()
+            .find_map(|(index, archive)| Some((index, *archive.index.get(name)?)))
+        else {
+            continue;
+        };
+
+        if pulled.insert((archive, member)) {
+            let member = &archives[archive].members[member];
+
+            declared.extend(member.globals());
+            pending.extend(member.externs());
+        }
+    }
+
+    pulled
+        .into_iter()
+        .map(|(archive, member)| &archives[archive].members[member])
+        .collect()
+}
+
+impl Display for Archive {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&toml::to_string(self).map_err(|_| std::fmt::Error)?)
+    }
+}
+
+impl FromStr for Archive {
+    type Err = toml::de::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        toml::from_str(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::{Archive, resolve};
+    use crate::tests::object;
+
+    #[test]
+    fn resolve_members() {
+        let archives = [Archive::new(vec![
+            object("global unused\nunused: halt"),
+            object("global print\nextern put\nprint: jmp put"),
+            object("global put\nput: halt"),
+            object("global put\nput: jmp put"),
+        ])];
+        let objects = [object("extern print, missing\njmp print\njmp missing")];
+        let pulled = resolve(&objects, &archives);
+
+        assert_eq!(archives[0].index["put"], 2);
+        assert_eq!(pulled.len(), 2);
+        assert_eq!(pulled[0].globals().collect::<Vec<_>>(), ["print"]);
+        assert_eq!(pulled[1].globals().collect::<Vec<_>>(), ["put"]);
+        assert_eq!(pulled[1].to_string(), archives[0].members[2].to_string());
+    }
+
+    #[test]
+    fn declared_labels() {
+        let archive = Archive::new(vec![object("global print\nprint: halt")]);
+        let objects = [
+            object("extern print\njmp print"),
+            object("global print\nprint: halt"),
+        ];
+
+        assert!(resolve(&objects, &[archive]).is_empty());
+    }
+
+    #[test]
+    fn round_trip() {
+        let archive = Archive::new(vec![object("global print\nprint: halt")]);
+        let parsed = Archive::from_str(&archive.to_string()).unwrap();
+
+        assert_eq!(parsed.index, archive.index);
+        assert_eq!(parsed.members.len(), 1);
+    }
+}
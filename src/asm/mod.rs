@@ -1,45 +1,54 @@
-use pest::Parser;
+use pest::{Parser, error::LineColLocation, iterators::Pair};
 
-use crate::ast::{Arguments, Instruction, Label, Macro, Statement};
+use crate::ast::{Arguments, Instruction, Label, Macro, Statement, parse_number};
 
 #[derive(pest_derive::Parser)]
 #[grammar = "./asm/syntax.pest"]
 pub struct AsmParser;
 
-pub fn parse_line(input: &str) -> Option<impl Iterator<Item = Statement<'_>>> {
-    let mut pairs = AsmParser::parse(Rule::line, input).ok()?;
+// `file` is the index of the source the line belongs to, lines with numbers that do not fit
+// in an address are not parsed, see `syntax_error`
+pub fn parse_line(input: &str, file: usize) -> Option<impl Iterator<Item = Statement<'_>>> {
+    let line = AsmParser::parse(Rule::line, input).ok()?.next().unwrap();
+
+    if overflowing_number(&line).is_some() {
+        return None;
+    }
 
     Some(
-        pairs
-            .next()
-            .unwrap()
-            .into_inner()
+        line.into_inner()
             .filter(|pair| pair.as_rule() != Rule::EOI)
-            .map(|pair| match pair.as_rule() {
+            .map(move |pair| match pair.as_rule() {
                 Rule::label => Statement::Label(Label {
                     pair,
                     registered: false,
+                    file,
+                    namespace: None,
+                    path: None,
+                    index: None,
+                    scoped: true,
                 }),
                 Rule::instruction => {
                     let mut pairs = pair.into_inner();
                     let name = pairs.next().unwrap();
-                    let is_macro = matches!(
-                        name.as_str().to_ascii_lowercase().as_str(),
-                        "string" | "var" | "static" | "alloc"
-                    );
+                    let directive = directive(name.as_str());
                     let arguments =
                         Arguments::from(pairs.map(|pair| pair.into()).collect::<Vec<_>>());
 
-                    if is_macro {
+                    if let Some(directive) = directive {
                         Statement::Macro(Macro {
                             pair: name,
+                            directive,
                             arguments,
                             is_valid: false,
+                            file,
+                            anchor: None,
                         })
                     } else {
                         Statement::Instruction(Instruction {
                             pair: name,
                             arguments,
+                            file,
                         })
                     }
                 }
@@ -47,3 +56,94 @@ pub fn parse_line(input: &str) -> Option<impl Iterator<Item = Statement<'_>>> {
             }),
     )
 }
+
+const DIRECTIVES: &[&str] = &[
+    "string",
+    "ascii",
+    "pstring",
+    "packed",
+    "var",
+    "word",
+    "fill",
+    "reserve",
+    "org",
+    "align",
+    "static",
+    "alloc",
+    "charmap",
+    "section",
+    "include",
+    "incbin",
+    "if",
+    "elif",
+    "else",
+    "endif",
+    "ifdef",
+    "ifndef",
+    "namespace",
+    "endnamespace",
+    "export",
+    "global",
+    "extern",
+];
+
+// directives are case insensitive, like mnemonics, the name is returned in lowercase
+fn directive(name: &str) -> Option<&'static str> {
+    DIRECTIVES
+        .iter()
+        .find(|directive| directive.eq_ignore_ascii_case(name))
+        .copied()
+}
+
+// column a line that does not parse stops at, or of a number too large in it
+pub fn syntax_error(input: &str) -> Option<usize> {
+    match AsmParser::parse(Rule::line, input) {
+        Ok(mut pairs) => overflowing_number(&pairs.next()?).map(|number| number.line_col().1),
+        Err(err) => match err.line_col {
+            LineColLocation::Pos((_, column)) | LineColLocation::Span((_, column), _) => {
+                Some(column)
+            }
+        },
+    }
+}
+
+fn overflowing_number<'i>(line: &Pair<'i, Rule>) -> Option<Pair<'i, Rule>> {
+    line.clone()
+        .into_inner()
+        .flatten()
+        .find(|pair| pair.as_rule() == Rule::number && parse_number(pair.as_str()).is_none())
+}
+
+// arguments of the instruction in a line as written, e.g. for macro invocations
+pub fn raw_arguments(input: &str) -> Option<Vec<&str>> {
+    let line = AsmParser::parse(Rule::line, input).ok()?.next()?;
+    let instruction = line
+        .into_inner()
+        .find(|pair| pair.as_rule() == Rule::instruction)?;
+
+    Some(
+        instruction
+            .into_inner()
+            .skip(1)
+            .map(|pair| pair.as_str())
+            .collect(),
+    )
+}
+
+// name and parameters of a macro definition header, `macro name a, b`
+pub fn parse_definition(input: &str) -> Option<(&str, Vec<&str>)> {
+    let definition = AsmParser::parse(Rule::definition, input).ok()?.next()?;
+    let mut words = definition
+        .into_inner()
+        .filter(|pair| pair.as_rule() == Rule::word)
+        .map(|pair| pair.as_str());
+
+    Some((words.next()?, words.collect()))
+}
+
+// value of a number written like a literal, without the `#`
+pub fn parse_literal(input: &str) -> Option<usize> {
+    let pairs = AsmParser::parse(Rule::number, input).ok()?;
+
+    (pairs.as_str() == input).then(|| parse_number(input))?
+}
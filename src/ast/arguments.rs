@@ -1,4 +1,4 @@
-use std::cmp::Ordering;
+use std::{cmp::Ordering, ops::RangeInclusive};
 
 use pest::iterators::Pair;
 
@@ -64,6 +64,13 @@ impl<'a> Arguments<'a> {
             }),
         }
     }
+
+    pub fn validate_argc_range(
+        &self,
+        range: RangeInclusive<usize>,
+    ) -> Result<(), ArgumentError<'a>> {
+        self.validate_argc(self.expr_list.len().clamp(*range.start(), *range.end()))
+    }
 }
 
 impl<'a, T> From<T> for Arguments<'a>
@@ -1,9 +1,12 @@
+use std::collections::HashMap;
+
 use pest::iterators::Pair;
 
 use super::{Reduce, ReduceError};
 use crate::{
     asm::Rule,
     context::{Argument, Context},
+    object::{Relocation, RelocationKind, Target},
 };
 
 #[derive(Debug, Clone)]
@@ -16,6 +19,10 @@ pub struct Compound<'a> {
 
 #[derive(Debug, Clone)]
 pub enum Expr<'a> {
+    Text {
+        pair: Pair<'a, Rule>,
+        value: Box<[u32]>,
+    },
     String {
         pair: Pair<'a, Rule>,
         value: Box<[u16]>,
@@ -24,10 +31,35 @@ pub enum Expr<'a> {
         pair: Pair<'a, Rule>,
         value: usize,
     },
+    // contents of a binary include, see `Sources::statements`
+    Binary {
+        pair: Pair<'a, Rule>,
+        value: &'a [u8],
+    },
     LabelRef {
         pair: Pair<'a, Rule>,
         name: &'a str,
     },
+    // reference to a local label, `.name`, qualified by the scope it is used in
+    ScopedRef {
+        pair: Pair<'a, Rule>,
+        name: &'a str,
+        path: Option<String>,
+    },
+    // reference to the closest numeric label in the given direction, `1f` or `1b`
+    LocalRef {
+        pair: Pair<'a, Rule>,
+        name: &'a str,
+        forward: bool,
+        // position of the referenced label among the ones with the same name
+        index: Option<usize>,
+    },
+    // address only known once the object is linked, see `Options::relocatable`
+    Relocatable {
+        pair: Pair<'a, Rule>,
+        target: Target,
+        addend: usize,
+    },
     Compound(Compound<'a>),
     Symbol {
         pair: Pair<'a, Rule>,
@@ -37,15 +69,144 @@ pub enum Expr<'a> {
 }
 
 impl<'a> Expr<'a> {
-    pub fn dependencies(&self) -> Vec<&str> {
+    // names the expression refers to, qualified once the scope is known, numeric labels come
+    // with their position among the ones with the same name
+    pub fn dependencies(&self) -> Vec<(&str, Option<usize>)> {
         match self {
             Self::Compound(Compound { lhs, rhs, .. }) => {
                 Iterator::chain(lhs.dependencies().into_iter(), rhs.dependencies()).collect()
             }
-            Self::LabelRef { name, .. } | Self::Symbol { name, .. } => [*name].into(),
+            Self::ScopedRef {
+                path: Some(path), ..
+            } => [(path.as_str(), None)].into(),
+            Self::LocalRef { name, index, .. } => [(*name, *index)].into(),
+            Self::LabelRef { name, .. }
+            | Self::ScopedRef { name, .. }
+            | Self::Symbol { name, .. } => [(*name, None)].into(),
             _ => [].into(),
         }
     }
+
+    // name of an unresolved label reference, qualified once the scope is known
+    pub fn label(&self) -> Option<&str> {
+        match self {
+            Self::LabelRef { name, .. } => Some(name),
+            Self::ScopedRef {
+                path: Some(path), ..
+            } => Some(path),
+            _ => None,
+        }
+    }
+
+    // value of expressions made only of integers and constants, known before any label is
+    pub fn constant(&self, constants: &HashMap<String, usize>) -> Option<usize> {
+        match self {
+            Self::Integer { value, .. } => Some(*value),
+            Self::LabelRef { name, .. } => constants.get(*name).copied(),
+            Self::Compound(Compound {
+                lhs, rhs, operator, ..
+            }) => {
+                let (x, y) = (lhs.constant(constants)?, rhs.constant(constants)?);
+
+                Some(match operator {
+                    Operator::Add => x.wrapping_add(y),
+                    Operator::Sub => x.wrapping_sub(y),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    // addresses in relocatable sections are relative to the section
+    fn address(pair: Pair<'a, Rule>, value: usize, section: Option<&String>) -> Self {
+        match section {
+            Some(section) => Self::Relocatable {
+                pair,
+                target: Target::Section(section.clone()),
+                addend: value,
+            },
+            None => Self::Integer { value, pair },
+        }
+    }
+
+    // allocations and externs are only placed once linked
+    fn external(pair: Pair<'a, Rule>, name: &str) -> Self {
+        Self::Relocatable {
+            pair,
+            target: Target::Symbol(name.to_owned()),
+            addend: 0,
+        }
+    }
+
+    pub fn relocation(
+        &self,
+        section: Option<String>,
+        offset: usize,
+        kind: RelocationKind,
+        file: usize,
+    ) -> Option<Relocation> {
+        match self {
+            Self::Relocatable { target, addend, .. } => Some(Relocation {
+                section,
+                offset,
+                target: target.clone(),
+                addend: *addend,
+                kind,
+                file: Some(file),
+            }),
+            _ => None,
+        }
+    }
+}
+
+// none when the number does not fit in an address
+pub(crate) fn parse_number(number: &str) -> Option<usize> {
+    let (radix, digits) = match number.get(..2).map(str::to_ascii_lowercase).as_deref() {
+        Some("0x") => (16, &number[2..]),
+        Some("0o") => (8, &number[2..]),
+        Some("0b") => (2, &number[2..]),
+        Some("0d") => (10, &number[2..]),
+        _ => (10, number),
+    };
+
+    usize::from_str_radix(digits, radix).ok()
+}
+
+// decodes the escape sequences of a string literal body into code points
+fn unescape(string: &str) -> Box<[u32]> {
+    let mut buffer = Vec::with_capacity(string.len());
+    let mut chars = string.chars();
+
+    while let Some(chr) = chars.next() {
+        if chr != '\\' {
+            buffer.push(chr as u32);
+            continue;
+        }
+
+        let value = match chars.next().unwrap() {
+            '0' => 0,
+            'n' => '\n' as u32,
+            't' => '\t' as u32,
+            'r' => '\r' as u32,
+            'x' => {
+                let (digits, rest) = chars.as_str().split_at(2);
+                chars = rest.chars();
+
+                u32::from_str_radix(digits, 16).unwrap()
+            }
+            'u' => {
+                let (digits, rest) = chars.as_str()[1..].split_once('}').unwrap();
+                chars = rest.chars();
+
+                u32::from_str_radix(digits, 16).unwrap()
+            }
+            next => next as u32,
+        };
+
+        buffer.push(value);
+    }
+
+    buffer.into_boxed_slice()
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -58,75 +219,74 @@ impl<'a> From<Pair<'a, Rule>> for Expr<'a> {
     fn from(pair: Pair<'a, Rule>) -> Self {
         match pair.as_rule() {
             Rule::argument => pair.into_inner().next().unwrap().into(),
+            Rule::ident if pair.as_str().starts_with('.') => Self::ScopedRef {
+                name: pair.as_str(),
+                path: None,
+                pair,
+            },
             Rule::ident | Rule::word => Self::LabelRef {
                 name: pair.as_str(),
                 pair,
             },
+            Rule::local_ref => {
+                let (name, direction) = pair.as_str().split_at(pair.as_str().len() - 1);
+
+                Self::LocalRef {
+                    name,
+                    forward: direction == "f",
+                    index: None,
+                    pair,
+                }
+            }
+            // lines with numbers that do not fit are not built, see `parse_line`
             Rule::number => Self::Integer {
-                value: pair.as_str().parse().unwrap(),
+                value: parse_number(pair.as_str()).unwrap(),
                 pair,
             },
             Rule::char => {
                 let string = pair.as_str();
-                let string = &string[1..(string.len() - 1)];
-                let mut chars = string.chars();
-                let mut chr = chars.next().unwrap() as usize;
-
-                if chr == '\\' as usize {
-                    let next = chars.next().unwrap();
+                let value = unescape(&string[1..(string.len() - 1)])
+                    .first()
+                    .copied()
+                    .unwrap_or(0);
 
-                    chr = match next {
-                        '\\' | '\'' => next as usize,
-                        _ => string[1..].parse().unwrap(),
-                    };
+                Self::Integer {
+                    value: value as usize,
+                    pair,
                 }
-
-                Self::Integer { value: chr, pair }
             }
+            // the grammar nests operators to the right, they are folded to the left so that
+            // `a - b - c` is `(a - b) - c`
             Rule::expr => {
                 let mut pairs = pair.clone().into_inner();
-                let lhs = pairs.next().unwrap().into();
+                let mut expr = pairs.next().unwrap().into();
 
-                if let Some(operator) = pairs.next() {
-                    let rhs = pairs.next().unwrap().into();
+                while let Some(operator) = pairs.next() {
+                    let mut rest = pairs.next().unwrap().into_inner();
+                    let rhs = rest.next().unwrap().into();
                     let operator = match operator.as_str() {
                         "+" => Operator::Add,
                         "-" => Operator::Sub,
                         _ => unreachable!(),
                     };
 
-                    Self::Compound(Compound {
-                        pair,
-                        lhs: Box::new(lhs),
+                    expr = Self::Compound(Compound {
+                        pair: pair.clone(),
+                        lhs: Box::new(expr),
                         rhs: Box::new(rhs),
                         operator,
-                    })
-                } else {
-                    lhs
+                    });
+                    pairs = rest;
                 }
+
+                expr
             }
             Rule::string => {
                 let string = pair.as_str();
-                let string = &string[1..(string.len() - 1)];
-
-                let mut buffer = Vec::with_capacity(string.len() + 1);
-                let mut chars = string.bytes();
-
-                while let Some(chr) = chars.next() {
-                    match chr {
-                        b'\\' => match chars.next().unwrap() {
-                            b'0' => buffer.push(0),
-                            next => buffer.push(next as u16),
-                        },
-                        chr => buffer.push(chr as u16),
-                    }
-                }
-
-                buffer.push(0);
 
-                Self::String {
+                Self::Text {
+                    value: unescape(&string[1..(string.len() - 1)]),
                     pair,
-                    value: buffer.into_boxed_slice(),
                 }
             }
             Rule::EOI => Self::Eoi,
@@ -161,6 +321,48 @@ impl<'a> Reduce for Expr<'a> {
                             pair,
                         })
                     }
+                    (Self::Relocatable { target, addend, .. }, Self::Integer { value, .. }, op) => {
+                        Ok(Self::Relocatable {
+                            pair,
+                            target,
+                            addend: match op {
+                                Operator::Add => addend.wrapping_add(value),
+                                Operator::Sub => addend.wrapping_sub(value),
+                            },
+                        })
+                    }
+                    (
+                        Self::Integer { value, .. },
+                        Self::Relocatable { target, addend, .. },
+                        Operator::Add,
+                    ) => Ok(Self::Relocatable {
+                        pair,
+                        target,
+                        addend: value.wrapping_add(addend),
+                    }),
+                    // distances inside a section are known before linking
+                    (
+                        Self::Relocatable {
+                            target: lhs @ Target::Section(_),
+                            addend: x,
+                            ..
+                        },
+                        Self::Relocatable {
+                            target: rhs,
+                            addend: y,
+                            ..
+                        },
+                        Operator::Sub,
+                    ) if lhs == rhs => Ok(Self::Integer {
+                        value: x.wrapping_sub(y),
+                        pair,
+                    }),
+                    (lhs @ Self::Relocatable { .. }, rhs, _)
+                    | (lhs, rhs @ Self::Relocatable { .. }, _)
+                        if lhs.is_reduced() && rhs.is_reduced() =>
+                    {
+                        Err(ReduceError::InvalidRelocation(pair))
+                    }
                     (lhs, rhs, operator) => Ok(Self::Compound(Compound {
                         pair,
                         lhs: Box::new(lhs),
@@ -172,24 +374,90 @@ impl<'a> Reduce for Expr<'a> {
             Self::LabelRef { name, pair } => {
                 if ctx.is.get_symbol(name).is_some() {
                     Ok(Self::Symbol { name, pair })
-                } else if let Some(Some(address)) = ctx.labels.get(name) {
+                } else if let Some(value) = ctx.constants.get(name) {
                     Ok(Self::Integer {
-                        value: *address,
+                        value: *value,
                         pair,
                     })
+                } else if let Some(Some(address)) = ctx.labels.get(name)
+                    && !ctx.provisional.contains_key(name)
+                {
+                    Ok(Self::address(pair, *address, ctx.label_sections.get(name)))
+                } else if ctx.is_external(name) {
+                    Ok(Self::external(pair, name))
                 } else if ctx.counter > 0 {
                     Ok(Self::LabelRef { pair, name })
                 } else {
                     Err(ReduceError::UnknownIdentifier(pair))
                 }
             }
+            // the scope is taken the first time the reference is reduced, which happens in the
+            // first pass, in order
+            Self::ScopedRef { pair, name, path } => {
+                let path = match path {
+                    Some(path) => path,
+                    None => ctx
+                        .get_path(name)
+                        .map_err(|err| ReduceError::from_label_err(err, pair.clone()))?,
+                };
+
+                match ctx.labels.get(&path) {
+                    Some(Some(address)) if !ctx.provisional.contains_key(&path) => {
+                        Ok(Self::address(pair, *address, ctx.label_sections.get(&path)))
+                    }
+                    _ if ctx.is_external(&path) => Ok(Self::external(pair, &path)),
+                    _ if ctx.counter > 0 => Ok(Self::ScopedRef {
+                        pair,
+                        name,
+                        path: Some(path),
+                    }),
+                    _ => Err(ReduceError::UnknownIdentifier(pair)),
+                }
+            }
+            // the referenced label is picked the first time the reference is reduced, which
+            // happens in the first pass, in order
+            Self::LocalRef {
+                pair,
+                name,
+                forward,
+                index,
+            } => {
+                let defined = ctx.numeric_labels.get(name).map_or(0, Vec::len);
+                let index = index.or(if forward {
+                    Some(defined)
+                } else {
+                    defined.checked_sub(1)
+                });
+                let address = index.and_then(|index| ctx.numeric_labels.get(name)?.get(index));
+
+                match (address, index) {
+                    (Some(label), _) if label.anchor.is_none() => {
+                        Ok(Self::address(pair, label.address, label.section.as_ref()))
+                    }
+                    (_, Some(_)) if ctx.counter > 0 => Ok(Self::LocalRef {
+                        pair,
+                        name,
+                        forward,
+                        index,
+                    }),
+                    _ => Err(ReduceError::UnknownIdentifier(pair)),
+                }
+            }
+            Self::Text { pair, value } => match ctx.encode(&value) {
+                Ok(value) => Ok(Self::String { pair, value }),
+                Err(err) => Err(ReduceError::from_charmap_err(err, pair)),
+            },
             _ => Ok(self),
         }
     }
 
     fn is_reduced(&self) -> bool {
         match self {
-            Self::Integer { .. } | Self::String { .. } | Self::Symbol { .. } => true,
+            Self::Integer { .. }
+            | Self::String { .. }
+            | Self::Binary { .. }
+            | Self::Relocatable { .. }
+            | Self::Symbol { .. } => true,
             Self::Compound(Compound { lhs, rhs, .. }) => lhs.is_reduced() && rhs.is_reduced(),
             _ => false,
         }
@@ -202,8 +470,13 @@ impl<'a> Expr<'a> {
             Self::Compound(expr) => expr.pair.clone(),
             Self::Integer { pair, .. }
             | Self::LabelRef { pair, .. }
+            | Self::ScopedRef { pair, .. }
+            | Self::LocalRef { pair, .. }
             | Self::Symbol { pair, .. }
-            | Self::String { pair, .. } => pair.clone(),
+            | Self::Text { pair, .. }
+            | Self::String { pair, .. }
+            | Self::Binary { pair, .. }
+            | Self::Relocatable { pair, .. } => pair.clone(),
             _ => unimplemented!(),
         }
     }
@@ -222,7 +495,8 @@ impl<'a> Expr<'a> {
                     })
                 }
             }
-            Self::Integer { value, .. } => {
+            // relocated addresses are filled in once linked, see `RelocationKind::apply`
+            Self::Integer { value, .. } | Self::Relocatable { addend: value, .. } => {
                 if let Some((kind, _bits)) = arg.r#type.split_once(|c: char| c.is_ascii_digit()) {
                     // let size = bits.parse().unwrap();
                     let value = match kind {
@@ -239,7 +513,7 @@ impl<'a> Expr<'a> {
                 if let Some((kind, _bits)) = arg.r#type.split_once(|c: char| c.is_ascii_digit()) {
                     // let size = bits.parse().unwrap();
                     let value = match kind {
-                        "u" | "i" | "ptr" => value[0],
+                        "u" | "i" | "ptr" => value.first().copied().unwrap_or(0),
                         _ => unimplemented!(),
                     };
 
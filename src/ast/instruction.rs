@@ -1,12 +1,13 @@
 use pest::iterators::Pair;
 
 use super::{Reduce, ReduceError, Statement, arguments::Arguments};
-use crate::{asm::Rule, context::Context};
+use crate::{asm::Rule, context::Context, object::RelocationKind};
 
 #[derive(Debug)]
 pub struct Instruction<'a> {
     pub arguments: Arguments<'a>,
     pub pair: Pair<'a, Rule>,
+    pub file: usize,
 }
 
 impl<'a> Reduce for Instruction<'a> {
@@ -19,17 +20,25 @@ impl<'a> Reduce for Instruction<'a> {
         if let Some(mnemonics) = mnemonics {
             // assumes a instruction cannot have different sizes based on input
             let size = mnemonics[0].length / 16;
-            ctx.address += size;
+            let address = ctx.address;
+            ctx.advance(size);
 
-            if self.is_reduced() {
+            let instruction = Instruction {
+                arguments: self.arguments.reduce(ctx)?,
+                ..self
+            };
+
+            if instruction.is_reduced() {
                 let result = mnemonics
                     .iter()
                     .map(|mnemonic| {
-                        self.arguments
+                        instruction
+                            .arguments
                             .validate_argc(mnemonic.argc())
-                            .map_err(|err| err.to_reduce_err(self.pair.clone()))?;
+                            .map_err(|err| err.to_reduce_err(instruction.pair.clone()))?;
 
-                        self.arguments
+                        instruction
+                            .arguments
                             .iter()
                             .zip(&mnemonic.arguments)
                             .try_fold(mnemonic.value, |acc, (expr, arg)| {
@@ -38,24 +47,36 @@ impl<'a> Reduce for Instruction<'a> {
                     })
                     .collect::<Vec<Result<_, ReduceError>>>();
 
-                if let Some(result) = result.iter().find(|x| x.is_ok()) {
-                    let bytes = result.as_ref().unwrap().to_be_bytes();
+                if let Some(index) = result.iter().position(|x| x.is_ok()) {
+                    let bytes = result[index].as_ref().unwrap().to_be_bytes();
+                    let section = ctx.relocatable_section();
+
+                    ctx.relocations.extend(
+                        instruction
+                            .arguments
+                            .iter()
+                            .zip(&mnemonics[index].arguments)
+                            .filter_map(|(expr, arg)| {
+                                let kind = RelocationKind::new(arg, size);
+
+                                expr.relocation(section.clone(), address, kind, instruction.file)
+                            }),
+                    );
 
                     let data = bytes[(4 - 2 * size)..]
                         .chunks_exact(2)
                         .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
                         .collect::<Vec<u16>>();
-                    Ok(Some(Statement::Data(data.into_boxed_slice(), None)))
+                    Ok(Some(Statement::Data(
+                        data.into_boxed_slice(),
+                        None,
+                        instruction.pair.clone(),
+                    )))
                 } else {
                     todo!()
                 }
             } else {
-                let arguments = self.arguments.reduce(ctx)?;
-
-                Ok(Some(Statement::Instruction(Instruction {
-                    arguments,
-                    ..self
-                })))
+                Ok(Some(Statement::Instruction(instruction)))
             }
         } else {
             Err(ReduceError::UnknownInstruction(self.pair))
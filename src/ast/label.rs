@@ -1,24 +1,78 @@
 use pest::iterators::Pair;
 
 use super::{Reduce, ReduceError, Statement};
-use crate::{asm::Rule, context::Context};
+use crate::{
+    asm::Rule,
+    context::{Context, NumericLabel},
+};
 
 #[derive(Debug, Clone)]
 pub struct Label<'a> {
     pub pair: Pair<'a, Rule>,
     pub registered: bool,
+    pub file: usize,
+    // namespace the label was declared in, see `resolve_namespaces`
+    pub namespace: Option<String>,
+    // full path, or position among the numeric labels with the same name, once registered,
+    // provisional labels are declared again in the next passes, see `Context::provisional`
+    pub path: Option<String>,
+    pub index: Option<usize>,
+    // labels made unique to a macro expansion do not open a scope for the local labels after
+    // them, see `Sources::expand`
+    pub scoped: bool,
 }
 
 impl<'a> Reduce for Label<'a> {
     type Error = ReduceError<'a>;
     type Output = Option<Statement<'a>>;
 
-    fn reduce(self, ctx: &mut Context) -> Result<Self::Output, Self::Error> {
+    fn reduce(mut self, ctx: &mut Context) -> Result<Self::Output, Self::Error> {
         let label = self.pair.as_str();
 
-        match ctx.register_label(label, self.registered) {
-            Ok(_) => Ok(None),
-            Err(_error) => Err(ReduceError::LabelRedeclaration { label: self.pair }),
+        // numeric labels can be declared many times, see `Expr::LocalRef`
+        if label.bytes().all(|chr| chr.is_ascii_digit()) {
+            let entry = NumericLabel {
+                address: ctx.address,
+                section: ctx.relocatable_section(),
+                anchor: ctx.anchor,
+            };
+            let provisional = entry.anchor.is_some();
+            let labels = ctx.numeric_labels.entry(label.to_owned()).or_default();
+            let index = *self.index.get_or_insert(labels.len());
+
+            match labels.get_mut(index) {
+                Some(label) => *label = entry,
+                None => labels.push(entry),
+            }
+
+            return Ok(provisional.then_some(Statement::Label(self)));
         }
+
+        let path = match self.path.take() {
+            Some(path) => path,
+            None => {
+                let label = match &self.namespace {
+                    Some(namespace) if !label.starts_with('.') => format!("{namespace}::{label}"),
+                    _ => label.to_owned(),
+                };
+
+                ctx.get_path(&label)
+                    .map_err(|err| ReduceError::from_label_err(err, self.pair.clone()))?
+            }
+        };
+
+        if let Err(err) = ctx.register_label(&path, self.file, self.registered, self.scoped) {
+            return Err(ReduceError::from_label_err(err, self.pair));
+        }
+
+        if !ctx.provisional.contains_key(&path) {
+            return Ok(None);
+        }
+
+        Ok(Some(Statement::Label(Self {
+            registered: true,
+            path: Some(path),
+            ..self
+        })))
     }
 }
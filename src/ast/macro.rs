@@ -1,87 +1,737 @@
 use pest::iterators::Pair;
 
-use crate::{asm::Rule, context::Context};
+use crate::{
+    asm::Rule,
+    context::{Condition, Context},
+    object::RelocationKind,
+};
 
-use super::{Expr, Reduce, ReduceError, Statement, arguments::Arguments};
+use super::{Dependencies, Expr, Reduce, ReduceError, Statement, arguments::Arguments};
 
 #[derive(Debug)]
 pub struct Macro<'a> {
     pub arguments: Arguments<'a>,
     pub pair: Pair<'a, Rule>,
+    // name of the directive in lowercase, see `asm::parse_line`
+    pub directive: &'static str,
     pub is_valid: bool,
+    pub file: usize,
+    // size or position the addresses after it are relative to, when the first pass could not
+    // reduce it, see `Context::anchors`
+    pub anchor: Option<usize>,
 }
 
 impl<'a> Reduce for Macro<'a> {
     type Output = Option<Statement<'a>>;
     type Error = ReduceError<'a>;
 
-    // static addr, data
+    // static addr, values+
     // alloc addr, size
-    // string data
-    // var size
+    // string data, attribute?
+    // ascii data, attribute?
+    // pstring data, attribute?
+    // packed data
+    // var size, values*
+    // word values+
+    // fill count, value
+    // reserve count
+    // org addr
+    // align alignment
+    // charmap name
+    // section name, (addr | region)?
+    // include path
+    // incbin path, offset?, length?, (be | le | byte)?
+    // if condition
+    // elif condition
+    // else
+    // endif
+    // ifdef name
+    // ifndef name
+    // namespace name
+    // endnamespace
+    // export names+
+    // global names+
+    // extern names+
     fn reduce(self, ctx: &mut Context) -> Result<Self::Output, Self::Error> {
         if !self.is_valid {
-            let argc = match self.pair.as_str() {
-                "string" | "var" => 1,
-                "alloc" | "static" => 2,
-                _ => unreachable!(),
+            let argc = match self.directive {
+                "string" | "ascii" | "pstring" | "section" => 1..=2,
+                "incbin" => 1..=4,
+                "if" | "elif" | "ifdef" | "ifndef" => 1..=1,
+                "else" | "endif" => 0..=0,
+                "var" | "word" | "global" | "extern" => 1..=usize::MAX,
+                "packed" | "reserve" | "org" | "align" | "charmap" | "include" => 1..=1,
+                "static" => 2..=usize::MAX,
+                "alloc" | "fill" => 2..=2,
+                _ => return Err(ReduceError::UnknownInstruction(self.pair)),
             };
 
             // checks for argument count
             self.arguments
-                .validate_argc(argc)
+                .validate_argc_range(argc)
                 .map_err(|err| err.to_reduce_err(self.pair.clone()))?;
         }
 
+        // conditions are evaluated once, in the first pass, and blocks are known to be
+        // balanced, see `check_conditionals`
+        if let Some(directive) = self.conditional() {
+            // an `ifdef` or `ifndef` kept from the first pass, its label must still be undefined
+            if ctx.declared {
+                return match self.undefined_label(ctx)? {
+                    Some(_) => Ok(None),
+                    None => Err(ReduceError::UnresolvedCondition(
+                        self.arguments.expr_list[0].pair(),
+                    )),
+                };
+            }
+
+            match directive {
+                "if" | "ifdef" | "ifndef" => {
+                    let enclosing = ctx.is_active();
+                    let active = enclosing && self.condition(ctx)?;
+
+                    ctx.conditions.push(Condition {
+                        active,
+                        taken: active || !enclosing,
+                    });
+
+                    // the label may be declared further down, which is checked once all are
+                    if enclosing
+                        && directive != "if"
+                        && let Some(path) = self.undefined_label(ctx)?
+                        && let Expr::LabelRef { pair, name } | Expr::ScopedRef { pair, name, .. } =
+                            self.arguments.expr_list[0].clone()
+                    {
+                        return Ok(Some(Statement::Macro(Self {
+                            arguments: vec![Expr::ScopedRef {
+                                pair,
+                                name,
+                                path: Some(path),
+                            }]
+                            .into(),
+                            ..self
+                        })));
+                    }
+                }
+                "elif" => {
+                    let taken = ctx.conditions.last().unwrap().taken;
+                    let active = !taken && self.condition(ctx)?;
+
+                    *ctx.conditions.last_mut().unwrap() = Condition {
+                        active,
+                        taken: taken || active,
+                    };
+                }
+                "else" => {
+                    let condition = ctx.conditions.last_mut().unwrap();
+
+                    condition.active = !condition.taken;
+                    condition.taken = true;
+                }
+                _ => {
+                    ctx.conditions.pop();
+                }
+            }
+
+            return Ok(None);
+        }
+
+        // the charmap name is not an expression, so it must not be reduced
+        if self.directive == "charmap" {
+            return match &self.arguments.expr_list[0] {
+                Expr::LabelRef { name, pair } => ctx
+                    .set_charmap(name)
+                    .map(|_| None)
+                    .map_err(|err| ReduceError::from_charmap_err(err, pair.clone())),
+                _ => Err(ReduceError::TypeError),
+            };
+        }
+
+        // the names are labels, which must not be reduced to their addresses, globals are
+        // checked once every label was declared
+        if let directive @ ("global" | "extern") = self.directive {
+            let mut names = Vec::with_capacity(self.arguments.expr_list.len());
+
+            for expr in self.arguments.expr_list {
+                let name = label_name(ctx, &expr)?;
+
+                match directive {
+                    "extern" => {
+                        ctx.externs.insert(name.clone());
+                    }
+                    _ if !ctx.declared => {
+                        ctx.globals.insert(name.clone());
+                    }
+                    _ if !ctx.labels.contains_key(&name) => {
+                        return Err(ReduceError::UnknownIdentifier(expr.pair()));
+                    }
+                    _ => {}
+                }
+
+                // keeps the scope the name was resolved in
+                if let Expr::LabelRef { pair, name: label }
+                | Expr::ScopedRef {
+                    pair, name: label, ..
+                } = expr
+                {
+                    names.push(Expr::ScopedRef {
+                        pair,
+                        name: label,
+                        path: Some(name),
+                    });
+                }
+            }
+
+            return Ok((directive == "global" && !ctx.declared).then(|| {
+                Statement::Macro(Self {
+                    arguments: names.into(),
+                    pair: self.pair,
+                    directive,
+                    is_valid: true,
+                    file: self.file,
+                    anchor: None,
+                })
+            }));
+        }
+
+        // sections are grouped by name before reduction, see `Macro::section`
+        if self.directive == "section" {
+            let Expr::LabelRef { name, .. } = &self.arguments.expr_list[0] else {
+                return Err(ReduceError::TypeError);
+            };
+
+            let mut in_region = false;
+            let address = match self.arguments.expr_list.get(1) {
+                None => None,
+                Some(Expr::LabelRef { name: region, .. })
+                    if ctx.is.get_region(region).is_some() =>
+                {
+                    // relocatable sections are placed into their region once linked
+                    if ctx.relocatable {
+                        ctx.section_regions
+                            .insert((*name).to_owned(), (*region).to_owned());
+                        None
+                    } else {
+                        in_region = true;
+                        Some(ctx.enter_region(region))
+                    }
+                }
+                Some(expr) => match expr.clone().reduce(ctx)? {
+                    Expr::Integer { value, .. } => {
+                        ctx.leave_region();
+                        Some(value)
+                    }
+                    _ => return Err(ReduceError::TypeError),
+                },
+            };
+
+            return match ctx.enter_section(name, address) {
+                // the region continues from a provisional address, see `Context::anchor`
+                Some(address) if in_region && ctx.anchor.is_some() => {
+                    ctx.address = address;
+
+                    Ok(Some(Statement::Macro(self)))
+                }
+                Some(address) => Statement::Origin(address).reduce(ctx),
+                None => Ok(None),
+            };
+        }
+
+        // the format is not an expression, so it must not be reduced
+        if self.directive == "incbin" {
+            let exprs = &self.arguments.expr_list;
+            let (format, argc) = match exprs.last() {
+                Some(Expr::LabelRef { name, .. }) if matches!(*name, "be" | "le" | "byte") => {
+                    (*name, exprs.len() - 1)
+                }
+                _ => ("be", exprs.len()),
+            };
+
+            let bytes = match &exprs[0] {
+                Expr::Binary { value, .. } => value,
+                Expr::Text { pair, .. } => return Err(ReduceError::MissingFile(pair.clone())),
+                _ => return Err(ReduceError::TypeError),
+            };
+
+            let mut range = Vec::with_capacity(2);
+
+            for expr in exprs.get(1..argc).unwrap_or_default() {
+                match expr.clone().reduce(ctx)? {
+                    Expr::Integer { value, .. } => range.push(value),
+                    _ => return Err(ReduceError::TypeError),
+                }
+            }
+
+            let offset = range.first().copied().unwrap_or(0);
+            let length = range
+                .get(1)
+                .copied()
+                .unwrap_or(bytes.len().saturating_sub(offset));
+
+            let Some(bytes) = bytes.get(offset..offset.saturating_add(length)) else {
+                return Err(ReduceError::BinaryRange {
+                    statement: self.pair,
+                    offset,
+                    length,
+                    size: bytes.len(),
+                });
+            };
+
+            let data = layout_binary(format, bytes);
+
+            ctx.advance(data.len());
+
+            return Ok(Some(Statement::Data(data, None, self.pair)));
+        }
+
+        let affects_layout = self.affects_layout();
         let mut arguments = self.arguments.reduce(ctx)?;
+        let address = ctx.address;
+
+        // the size or position may depend on labels declared after it, the addresses after it
+        // are then provisional until it is known, and so is any alignment after them
+        let is_provisional = !arguments.expr_list[0].is_reduced()
+            || (self.directive == "align" && ctx.anchor.is_some());
+
+        if affects_layout && ctx.counter > 0 && is_provisional {
+            return Ok(Some(Statement::Macro(Self {
+                is_valid: true,
+                arguments,
+                anchor: Some(ctx.push_anchor()),
+                ..self
+            })));
+        }
 
         // checks if arguments are resolved and their types
-        match self.pair.as_str() {
-            "string" => match arguments.expr_list.pop().unwrap() {
-                Expr::String { value, .. } => {
-                    ctx.advance(value.len());
-                    Ok(Some(Statement::Data(value, None)))
+        match self.directive {
+            directive @ ("string" | "ascii" | "pstring" | "packed") => {
+                // the attribute is added to every character, e.g. a color offset
+                let attribute = match arguments.expr_list.get(1) {
+                    None => Some(0),
+                    Some(Expr::Integer { value, pair }) => match u16::try_from(*value) {
+                        Ok(value) => Some(value),
+                        Err(_) => {
+                            return Err(ReduceError::ValueOverflow {
+                                argument: pair.clone(),
+                                value: *value,
+                            });
+                        }
+                    },
+                    Some(expr) if !expr.is_reduced() => None,
+                    Some(_) => return Err(ReduceError::TypeError),
+                };
+
+                let data = match &arguments.expr_list[0] {
+                    Expr::String { value, pair } => {
+                        layout_string(directive, value, attribute.unwrap_or(0), pair.clone())?
+                    }
+                    _ => return Err(ReduceError::TypeError),
+                };
+
+                ctx.advance(data.len());
+
+                if attribute.is_some() {
+                    Ok(Some(Statement::Data(data, None, self.pair.clone())))
+                } else {
+                    Ok(Some(Statement::Macro(Self {
+                        is_valid: true,
+                        arguments,
+                        ..self
+                    })))
+                }
+            }
+            "var" => match &arguments.expr_list[0] {
+                Expr::Integer { value: size, .. } => {
+                    let size = *size;
+                    let values = &arguments.expr_list[1..];
+
+                    if values.len() > size {
+                        return Err(ReduceError::UnexpectedArgument {
+                            instruction: self.pair,
+                            arguments: values[size..].iter().map(|arg| arg.pair()).collect(),
+                            expected: size + 1,
+                            found: values.len() + 1,
+                        });
+                    }
+
+                    ctx.advance(size);
+
+                    match words(values)? {
+                        Some(data) if data.is_empty() => {
+                            Ok(Some(Statement::Reserve(size, None, self.pair.clone())))
+                        }
+                        // strings take a word per character, so may not fit even if the values do
+                        Some(data) if data.len() > size => Err(ReduceError::UnexpectedArgument {
+                            instruction: self.pair,
+                            arguments: values.iter().map(|arg| arg.pair()).collect(),
+                            expected: size + 1,
+                            found: data.len() + 1,
+                        }),
+                        Some(mut data) => {
+                            let section = ctx.relocatable_section();
+
+                            relocate(ctx, values, section, address, self.file);
+                            data.resize(size, 0);
+                            Ok(Some(Statement::Variable(
+                                data.into_boxed_slice(),
+                                None,
+                                self.pair.clone(),
+                            )))
+                        }
+                        None => Ok(Some(Statement::Macro(Self {
+                            is_valid: true,
+                            arguments,
+                            ..self
+                        }))),
+                    }
                 }
                 _ => Err(ReduceError::TypeError),
             },
-            "var" => match arguments.expr_list.pop().unwrap() {
-                Expr::Integer { value, .. } => Ok(Some(Statement::Data(
-                    {
-                        ctx.advance(value);
+            "word" => {
+                ctx.advance(arguments.expr_list.len());
 
-                        let vec = vec![0; value];
-                        vec.into_boxed_slice()
-                    },
-                    None,
-                ))),
+                match words(&arguments.expr_list)? {
+                    Some(data) => {
+                        let section = ctx.relocatable_section();
+
+                        relocate(ctx, &arguments.expr_list, section, address, self.file);
+
+                        Ok(Some(Statement::Data(
+                            data.into_boxed_slice(),
+                            None,
+                            self.pair.clone(),
+                        )))
+                    }
+                    None => Ok(Some(Statement::Macro(Self {
+                        is_valid: true,
+                        arguments,
+                        ..self
+                    }))),
+                }
+            }
+            "fill" => match &arguments.expr_list[0] {
+                Expr::Integer { value: count, .. } => {
+                    let count = *count;
+
+                    ctx.advance(count);
+
+                    match words(&arguments.expr_list[1..])? {
+                        Some(value) if value.len() == 1 => {
+                            let section = ctx.relocatable_section();
+
+                            for index in 0..count {
+                                relocate(
+                                    ctx,
+                                    &arguments.expr_list[1..],
+                                    section.clone(),
+                                    address + index,
+                                    self.file,
+                                );
+                            }
+
+                            Ok(Some(Statement::Data(
+                                vec![value[0]; count].into_boxed_slice(),
+                                None,
+                                self.pair.clone(),
+                            )))
+                        }
+                        Some(_) => Err(ReduceError::TypeError),
+                        None => Ok(Some(Statement::Macro(Self {
+                            is_valid: true,
+                            arguments,
+                            ..self
+                        }))),
+                    }
+                }
                 _ => Err(ReduceError::TypeError),
             },
-            "alloc" => match (
-                arguments.expr_list.pop().unwrap(),
-                arguments.expr_list.pop().unwrap(),
-            ) {
-                (Expr::Integer { value, .. }, Expr::LabelRef { name, pair }) => {
-                    ctx.allocate(name, Some(value), false)
-                        .map_err(|err| ReduceError::from_label_err(err, pair))?;
-
-                    Ok(None)
+            "reserve" => match arguments.expr_list.pop().unwrap() {
+                Expr::Integer { value, .. } => {
+                    ctx.advance(value);
+                    Ok(Some(Statement::Reserve(value, None, self.pair)))
                 }
                 _ => Err(ReduceError::TypeError),
             },
-            "static" => match (&arguments.expr_list[1], &arguments.expr_list[0]) {
-                (Expr::Integer { value, .. }, Expr::Integer { value: offset, .. }) => Ok(Some(
-                    Statement::Data(Box::new([*value as u16]), Some(*offset)),
-                )),
-                (Expr::String { value, .. }, Expr::Integer { value: offset, .. }) => {
-                    Ok(Some(Statement::Data(Box::new([value[0]]), Some(*offset))))
+            "alloc" => match (&arguments.expr_list[0], &arguments.expr_list[1]) {
+                (label, Expr::Integer { value, .. }) if label.label().is_some() => {
+                    ctx.allocate(label.label().unwrap(), Some(*value), ctx.declared)
+                        .map_err(|err| ReduceError::from_label_err(err, label.pair()))?;
+
+                    Ok(Some(Statement::Macro(Self {
+                        is_valid: true,
+                        arguments,
+                        ..self
+                    })))
                 }
-                _ => Ok(Some(Statement::Macro(Self {
+                // allocations of relocatable objects are placed once linked
+                (Expr::Relocatable { .. }, Expr::Integer { .. }) => Ok(None),
+                // once placed, the allocation is kept for the layout checks
+                (Expr::Integer { value: address, .. }, Expr::Integer { value, .. }) => Ok(Some(
+                    Statement::Reserve(*value, Some(*address), self.pair.clone()),
+                )),
+                _ => Err(ReduceError::TypeError),
+            },
+            "org" => match arguments.expr_list.pop().unwrap() {
+                Expr::Integer { value, .. } => Statement::Origin(value).reduce(ctx),
+                _ => Err(ReduceError::TypeError),
+            },
+            "align" => match arguments.expr_list.pop().unwrap() {
+                Expr::Integer { value, .. } if value > 0 => Statement::Align(value).reduce(ctx),
+                _ => Err(ReduceError::TypeError),
+            },
+            // found includes are replaced by the included statements, see `Sources::statements`
+            "include" => match arguments.expr_list.pop().unwrap() {
+                Expr::String { pair, .. } => Err(ReduceError::MissingFile(pair)),
+                _ => Err(ReduceError::TypeError),
+            },
+            "static" => match &arguments.expr_list[0] {
+                Expr::Integer { value: offset, .. } => match words(&arguments.expr_list[1..])? {
+                    Some(data) => {
+                        relocate(ctx, &arguments.expr_list[1..], None, *offset, self.file);
+
+                        Ok(Some(Statement::Data(
+                            data.into_boxed_slice(),
+                            Some(*offset),
+                            self.pair.clone(),
+                        )))
+                    }
+                    None => Ok(Some(Statement::Macro(Self {
+                        is_valid: true,
+                        arguments,
+                        ..self
+                    }))),
+                },
+                expr if !expr.is_reduced() => Ok(Some(Statement::Macro(Self {
                     is_valid: true,
                     arguments,
                     ..self
                 }))),
+                _ => Err(ReduceError::TypeError),
             },
-            _ => unreachable!(),
+            _ => Err(ReduceError::UnknownInstruction(self.pair)),
         }
     }
 }
+
+// labels the size or position depends on, see `Macro::affects_layout`
+impl Dependencies for Macro<'_> {
+    fn dependencies(&self) -> Vec<(&str, Option<usize>)> {
+        match self.arguments.expr_list.first() {
+            Some(expr) if self.affects_layout() => expr.dependencies(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl<'a> Macro<'a> {
+    pub fn section(&self) -> Option<&'a str> {
+        match (self.directive, self.arguments.expr_list.first()) {
+            ("section", Some(Expr::LabelRef { name, .. })) => Some(name),
+            _ => None,
+        }
+    }
+
+    // directives whose first argument is a size or position, which the addresses after
+    // them depend on
+    pub fn affects_layout(&self) -> bool {
+        matches!(self.directive, "var" | "fill" | "reserve" | "org" | "align")
+    }
+
+    pub fn conditional(&self) -> Option<&'a str> {
+        match self.directive {
+            directive @ ("if" | "elif" | "else" | "endif" | "ifdef" | "ifndef") => Some(directive),
+            _ => None,
+        }
+    }
+
+    // labels and symbols are defined once declared, constants are always defined, conditions must fold to an integer
+    // and cannot depend on labels declared after them
+    fn condition(&self, ctx: &mut Context) -> Result<bool, ReduceError<'a>> {
+        let expr = &self.arguments.expr_list[0];
+
+        match self.directive {
+            directive @ ("ifdef" | "ifndef") => {
+                Ok(self.undefined_label(ctx)?.is_none() == (directive == "ifdef"))
+            }
+            _ => match expr.clone().reduce(ctx)? {
+                Expr::Integer { value, .. } => Ok(value != 0),
+                expr if !expr.is_reduced() => Err(ReduceError::UnresolvedCondition(expr.pair())),
+                _ => Err(ReduceError::TypeError),
+            },
+        }
+    }
+
+    // path of the label an `ifdef` or `ifndef` refers to, unless it is defined
+    fn undefined_label(&self, ctx: &Context) -> Result<Option<String>, ReduceError<'a>> {
+        let name = label_name(ctx, &self.arguments.expr_list[0])?;
+        let defined = ctx.labels.contains_key(&name)
+            || ctx.constants.contains_key(&name)
+            || ctx.is.get_symbol(&name).is_some();
+
+        Ok((!defined).then_some(name))
+    }
+
+    pub fn namespace(&self) -> Option<&'a str> {
+        match self.directive {
+            directive @ ("namespace" | "endnamespace" | "export") => Some(directive),
+            _ => None,
+        }
+    }
+
+    pub fn include(&self) -> Option<String> {
+        self.path("include")
+            .filter(|_| self.arguments.expr_list.len() == 1)
+    }
+
+    pub fn incbin(&self) -> Option<String> {
+        self.path("incbin")
+    }
+
+    fn path(&self, directive: &str) -> Option<String> {
+        match self.arguments.expr_list.first() {
+            Some(Expr::Text { value, .. }) if self.directive == directive => Some(
+                value
+                    .iter()
+                    .filter_map(|chr| char::from_u32(*chr))
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+}
+
+// name of the label an expression refers to, in the scope it is used in
+fn label_name<'a>(ctx: &Context, expr: &Expr<'a>) -> Result<String, ReduceError<'a>> {
+    match expr {
+        Expr::LabelRef { name, pair } | Expr::ScopedRef { name, pair, .. } => match expr.label() {
+            Some(label) => Ok(label.to_owned()),
+            None => ctx
+                .get_path(name)
+                .map_err(|err| ReduceError::from_label_err(err, pair.clone())),
+        },
+        _ => Err(ReduceError::TypeError),
+    }
+}
+
+// strings are expanded into their characters, without a terminator
+// values must fit in a word, either unsigned or as a negative number that wrapped around
+// returns none while any of the values is still unresolved
+fn words<'a>(exprs: &[Expr<'a>]) -> Result<Option<Vec<u16>>, ReduceError<'a>> {
+    let mut data = Vec::with_capacity(exprs.len());
+
+    for expr in exprs {
+        match expr {
+            Expr::Integer { value, pair }
+            | Expr::Relocatable {
+                addend: value,
+                pair,
+                ..
+            } => {
+                let word = u16::try_from(*value)
+                    .or_else(|_| i16::try_from(*value as isize).map(|value| value as u16))
+                    .map_err(|_| ReduceError::ValueOverflow {
+                        argument: pair.clone(),
+                        value: *value,
+                    })?;
+
+                data.push(word)
+            }
+            Expr::String { value, .. } => data.extend_from_slice(value),
+            expr if !expr.is_reduced() => return Ok(None),
+            _ => return Err(ReduceError::TypeError),
+        }
+    }
+
+    Ok(Some(data))
+}
+
+// records the relocations of values laid out by `words` from the given address
+fn relocate(
+    ctx: &mut Context,
+    exprs: &[Expr],
+    section: Option<String>,
+    mut address: usize,
+    file: usize,
+) {
+    for expr in exprs {
+        match expr {
+            Expr::String { value, .. } => address += value.len(),
+            expr => {
+                ctx.relocations.extend(expr.relocation(
+                    section.clone(),
+                    address,
+                    RelocationKind::WORD,
+                    file,
+                ));
+                address += 1;
+            }
+        }
+    }
+}
+
+// string: one character per word, zero terminated
+// ascii: one character per word, unterminated
+// pstring: one character per word, prefixed by its length
+// packed: two characters per word, high byte first, zero terminated
+// the attribute is only added to characters, never to terminators or lengths
+fn layout_string<'a>(
+    directive: &str,
+    value: &[u16],
+    attribute: u16,
+    pair: Pair<'a, Rule>,
+) -> Result<Box<[u16]>, ReduceError<'a>> {
+    let chars = value.iter().map(|chr| chr.wrapping_add(attribute));
+
+    match directive {
+        "string" => Ok(chars.chain([0]).collect()),
+        "ascii" => Ok(chars.collect()),
+        "pstring" => {
+            let length = u16::try_from(value.len()).map_err(|_| ReduceError::ValueOverflow {
+                argument: pair,
+                value: value.len(),
+            })?;
+
+            Ok([length].into_iter().chain(chars).collect())
+        }
+        "packed" => {
+            if let Some(chr) = value.iter().find(|chr| **chr > 0xff) {
+                return Err(ReduceError::UnencodableChar {
+                    string: pair,
+                    code_point: *chr as u32,
+                });
+            }
+
+            Ok(value
+                .iter()
+                .copied()
+                .chain([0])
+                .collect::<Vec<_>>()
+                .chunks(2)
+                .map(|chunk| chunk[0] << 8 | chunk.get(1).copied().unwrap_or(0))
+                .collect())
+        }
+        _ => Err(ReduceError::TypeError),
+    }
+}
+
+// be: two bytes per word, high byte first
+// le: two bytes per word, low byte first
+// byte: one byte per word
+// an odd trailing byte is padded with zero
+fn layout_binary(format: &str, bytes: &[u8]) -> Box<[u16]> {
+    if format == "byte" {
+        return bytes.iter().map(|byte| *byte as u16).collect();
+    }
+
+    bytes
+        .chunks(2)
+        .map(|chunk| {
+            let pair = [chunk[0], chunk.get(1).copied().unwrap_or(0)];
+
+            match format {
+                "le" => u16::from_le_bytes(pair),
+                _ => u16::from_be_bytes(pair),
+            }
+        })
+        .collect()
+}
@@ -17,7 +17,7 @@ pub use statement::*;
 
 use crate::{
     asm::Rule,
-    context::{Context, LabelError},
+    context::{CharmapError, Context, LabelError, OccupyError, RegionAttribute},
 };
 
 #[derive(Debug)]
@@ -44,6 +44,64 @@ pub enum ReduceError<'a> {
     LabelRedeclaration {
         label: Pair<'a, Rule>,
     },
+    // label declared with the name of a label imported from a previous build
+    ImportConflict(Pair<'a, Rule>),
+    // local label outside of any scope it could belong to
+    InvalidLabel(Pair<'a, Rule>),
+    UnknownCharmap(Pair<'a, Rule>),
+    UnencodableChar {
+        string: Pair<'a, Rule>,
+        code_point: u32,
+    },
+    // value that does not fit in a word, e.g. the length of a long `pstring`
+    ValueOverflow {
+        argument: Pair<'a, Rule>,
+        value: usize,
+    },
+    Overlap {
+        address: usize,
+        previous: Pair<'a, Rule>,
+        current: Pair<'a, Rule>,
+    },
+    AddressOverflow {
+        statement: Pair<'a, Rule>,
+        address: usize,
+    },
+    UnmappedAddress {
+        statement: Pair<'a, Rule>,
+        address: usize,
+    },
+    RegionOverflow {
+        statement: Pair<'a, Rule>,
+        region: String,
+    },
+    InvalidRegion {
+        statement: Pair<'a, Rule>,
+        region: String,
+        attribute: RegionAttribute,
+    },
+    UnresolvedCondition(Pair<'a, Rule>),
+    UnbalancedNamespace(Pair<'a, Rule>),
+    // reference from outside of a namespace to a label it does not export
+    NotExported(Pair<'a, Rule>),
+    // relocatable address used where it cannot be relocated, e.g. the sum of two labels
+    InvalidRelocation(Pair<'a, Rule>),
+    UnbalancedConditional(Pair<'a, Rule>),
+    // included file that was not found, in a block that could not be decided while loading
+    MissingFile(Pair<'a, Rule>),
+    SectionInConditional(Pair<'a, Rule>),
+    BinaryRange {
+        statement: Pair<'a, Rule>,
+        offset: usize,
+        length: usize,
+        size: usize,
+    },
+    // labels whose addresses depend on each other, e.g. through the count of a `fill` between
+    // them, the first label of the cycle is repeated at its end
+    CyclicDefinition {
+        statement: Pair<'a, Rule>,
+        cycle: Vec<String>,
+    },
 }
 
 pub trait Reduce {
@@ -58,16 +116,95 @@ pub trait Reduce {
 }
 
 pub trait Dependencies {
-    fn dependencies(&self) -> Vec<&str>;
+    fn dependencies(&self) -> Vec<(&str, Option<usize>)>;
 }
 
 impl<'a> ReduceError<'a> {
+    // the source the error points at
+    pub fn pair(&self) -> Option<&Pair<'a, Rule>> {
+        match self {
+            Self::UnknownInstruction(pair)
+            | Self::UnknownIdentifier(pair)
+            | Self::UnknownCharmap(pair)
+            | Self::InvalidLabel(pair)
+            | Self::ImportConflict(pair)
+            | Self::UnresolvedCondition(pair)
+            | Self::UnbalancedNamespace(pair)
+            | Self::NotExported(pair)
+            | Self::InvalidRelocation(pair)
+            | Self::UnbalancedConditional(pair)
+            | Self::MissingFile(pair)
+            | Self::SectionInConditional(pair)
+            | Self::UnexpectedArgument {
+                instruction: pair, ..
+            }
+            | Self::ExpectedArgument {
+                instruction: pair, ..
+            }
+            | Self::ExpectedType { argument: pair, .. }
+            | Self::LabelRedeclaration { label: pair }
+            | Self::UnencodableChar { string: pair, .. }
+            | Self::ValueOverflow { argument: pair, .. }
+            | Self::Overlap { current: pair, .. }
+            | Self::AddressOverflow {
+                statement: pair, ..
+            }
+            | Self::UnmappedAddress {
+                statement: pair, ..
+            }
+            | Self::RegionOverflow {
+                statement: pair, ..
+            }
+            | Self::InvalidRegion {
+                statement: pair, ..
+            }
+            | Self::BinaryRange {
+                statement: pair, ..
+            }
+            | Self::CyclicDefinition {
+                statement: pair, ..
+            } => Some(pair),
+            Self::TypeError => None,
+        }
+    }
+
     pub fn from_label_err(err: LabelError, label: Pair<'a, Rule>) -> Self {
         match err {
             LabelError::Unavailable => Self::LabelRedeclaration { label },
-            LabelError::InvalidLabel => {
-                todo!()
-            }
+            LabelError::Imported => Self::ImportConflict(label),
+            LabelError::InvalidLabel => Self::InvalidLabel(label),
+        }
+    }
+
+    pub fn from_occupy_err(
+        err: OccupyError<Pair<'a, Rule>>,
+        statement: Pair<'a, Rule>,
+        address: usize,
+    ) -> Self {
+        match err {
+            OccupyError::AddressOverflow => Self::AddressOverflow { statement, address },
+            OccupyError::UnmappedAddress => Self::UnmappedAddress { statement, address },
+            OccupyError::RegionOverflow(region) => Self::RegionOverflow { statement, region },
+            OccupyError::InvalidRegion(region, attribute) => Self::InvalidRegion {
+                statement,
+                region,
+                attribute,
+            },
+            OccupyError::Overlap(address, previous) => Self::Overlap {
+                address,
+                previous,
+                current: statement,
+            },
+        }
+    }
+
+    pub fn from_charmap_err(err: CharmapError, pair: Pair<'a, Rule>) -> Self {
+        match err {
+            CharmapError::UnknownCharmap => Self::UnknownCharmap(pair),
+            CharmapError::Unencodable(code_point) => Self::UnencodableChar {
+                string: pair,
+                code_point,
+            },
         }
     }
 }
@@ -1,4 +1,9 @@
-use crate::context::Context;
+use pest::iterators::Pair;
+
+use crate::{
+    asm::Rule,
+    context::{Context, InstructionSet, Occupied, RegionAttribute},
+};
 
 use super::{Instruction, Label, Macro, Reduce, ReduceError};
 
@@ -7,7 +12,12 @@ pub enum Statement<'a> {
     Label(Label<'a>),
     Instruction(Instruction<'a>),
     Macro(Macro<'a>),
-    Data(Box<[u16]>, Option<usize>),
+    Data(Box<[u16]>, Option<usize>, Pair<'a, Rule>),
+    Variable(Box<[u16]>, Option<usize>, Pair<'a, Rule>),
+    Reserve(usize, Option<usize>, Pair<'a, Rule>),
+    Origin(usize),
+    Align(usize),
+    Section(&'a str),
 }
 
 impl<'a> Reduce for Statement<'a> {
@@ -15,37 +25,139 @@ impl<'a> Reduce for Statement<'a> {
     type Error = ReduceError<'a>;
 
     fn reduce(self, ctx: &mut Context) -> Result<Self::Output, Self::Error> {
+        let is_conditional =
+            matches!(&self, Self::Macro(r#macro) if r#macro.conditional().is_some());
+
+        if !is_conditional && !ctx.is_active() {
+            return Ok(None);
+        }
+
         match self {
             Self::Instruction(instruction) => instruction.reduce(ctx),
             Self::Label(label) => label.reduce(ctx),
             Self::Macro(r#macro) => r#macro.reduce(ctx),
-            Self::Data(data, offset) => {
+            Self::Data(data, offset, pair) => {
+                if offset.is_none() {
+                    ctx.advance(data.len());
+                }
+
+                Ok(Some(Self::Data(data, offset, pair)))
+            }
+            Self::Variable(data, offset, pair) => {
                 if offset.is_none() {
                     ctx.advance(data.len());
                 }
 
-                Ok(Some(Self::Data(data, offset)))
+                Ok(Some(Self::Variable(data, offset, pair)))
+            }
+            Self::Reserve(len, offset, pair) => {
+                if offset.is_none() {
+                    ctx.advance(len);
+                }
+
+                Ok(Some(Self::Reserve(len, offset, pair)))
+            }
+            Self::Origin(address) => {
+                ctx.address = address;
+                ctx.anchor = None;
+
+                Ok(Some(Self::Origin(address)))
+            }
+            Self::Align(alignment) => {
+                ctx.advance(ctx.address.next_multiple_of(alignment) - ctx.address);
+
+                Ok(Some(Self::Align(alignment)))
+            }
+            // relocatable sections keep their names for `Object::build`
+            Self::Section(name) if ctx.relocatable => {
+                ctx.enter_section(name, None);
+                ctx.address = 0;
+                ctx.anchor = None;
+
+                Ok(Some(Self::Section(name)))
+            }
+            // sections are entered again while any address is provisional, to update their
+            // bounds, and regions continue from where the last provisional section left them
+            Self::Section(name) => {
+                let address = ctx.enter_section(name, None);
+
+                if let Some(address) = address {
+                    ctx.address = address;
+                }
+
+                if !ctx.declared || !ctx.is_settled() {
+                    return Ok(Some(Self::Section(name)));
+                }
+
+                Ok(address.map(Self::Origin))
             }
         }
     }
 }
 
+pub struct Layout<'a, 'is> {
+    pub buffer: Box<[u16]>,
+    pub is: &'is InstructionSet,
+    pub occupied: Occupied<Pair<'a, Rule>>,
+}
+
+impl<'a, 'is> Layout<'a, 'is> {
+    pub fn new(is: &'is InstructionSet) -> Self {
+        Self {
+            buffer: Box::new([0u16; 0x10000]),
+            is,
+            occupied: Occupied::default(),
+        }
+    }
+
+    // `forbidden` lists the region attributes the range cannot be placed in
+    pub fn occupy(
+        &mut self,
+        address: usize,
+        len: usize,
+        pair: &Pair<'a, Rule>,
+        forbidden: &[RegionAttribute],
+    ) -> Result<usize, ReduceError<'a>> {
+        let size = self.buffer.len();
+
+        self.occupied
+            .occupy(self.is, address, len, size, forbidden, pair.clone())
+            .map_err(|err| ReduceError::from_occupy_err(err, pair.clone(), address))
+    }
+}
+
 impl<'a> Statement<'a> {
-    pub fn copy(&self, buffer: &mut [u16], index: usize) -> usize {
+    pub fn copy(
+        &self,
+        layout: &mut Layout<'a, '_>,
+        index: usize,
+    ) -> Result<usize, ReduceError<'a>> {
+        use RegionAttribute::*;
+
         match self {
-            Self::Data(data, offset) => {
-                if let Some(address) = *offset {
-                    buffer[address..(address + data.len())].copy_from_slice(data);
-                    index
-                } else {
-                    buffer[index..(index + data.len())].copy_from_slice(data);
-                    index + data.len()
-                }
+            Self::Data(data, offset, pair) | Self::Variable(data, offset, pair) => {
+                let forbidden: &[_] = match self {
+                    Self::Data(..) => &[Uninitialized, Io],
+                    _ => &[ReadOnly, Uninitialized, Io],
+                };
+
+                let address = offset.unwrap_or(index);
+                let end = layout.occupy(address, data.len(), pair, forbidden)?;
+
+                layout.buffer[address..end].copy_from_slice(data);
+
+                Ok(if offset.is_some() { index } else { end })
             }
-            _ => {
-                println!("test");
-                0
+            Self::Reserve(len, offset, pair) => {
+                let address = offset.unwrap_or(index);
+                let end = layout.occupy(address, *len, pair, &[ReadOnly])?;
+
+                Ok(if offset.is_some() { index } else { end })
             }
+            Self::Origin(address) => Ok(*address),
+            Self::Align(alignment) => Ok(index.next_multiple_of(*alignment)),
+            // labels, instructions, macros and sections are reduced before being copied
+            _ => unreachable!(),
         }
     }
 }
@@ -1,4 +1,9 @@
-use std::{collections::HashMap, str::FromStr};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    str::FromStr,
+};
+
+use serde::de::Error;
 
 #[derive(serde_derive::Deserialize, Debug)]
 pub struct Instruction {
@@ -36,6 +41,12 @@ impl Argument {
 pub struct InstructionSet {
     pub symbols: HashMap<String, Symbol>,
     pub instructions: HashMap<String, Vec<Instruction>>,
+    #[serde(default)]
+    pub charmaps: HashMap<String, Charmap>,
+    #[serde(default)]
+    pub regions: HashMap<String, Region>,
+    #[serde(default)]
+    pub placement: HashMap<String, Placement>,
 }
 
 impl InstructionSet {
@@ -50,6 +61,40 @@ impl InstructionSet {
         let name = name.to_ascii_lowercase();
         self.instructions.get(&name).map(std::ops::Deref::deref)
     }
+
+    pub fn get_charmap(&self, name: &str) -> Option<&Charmap> {
+        self.charmaps.get(name)
+    }
+
+    pub fn get_region(&self, name: &str) -> Option<&Region> {
+        self.regions.get(name)
+    }
+
+    // returns the region the section is placed in and its position among the region sections
+    pub fn find_placement(&self, section: &str) -> Option<(&str, usize)> {
+        self.placement.iter().find_map(|(region, placement)| {
+            placement
+                .sections
+                .iter()
+                .position(|name| name == section)
+                .map(|index| (region.as_str(), index))
+        })
+    }
+
+    pub fn allocation_region(&self) -> Option<(&str, &Region)> {
+        self.placement
+            .iter()
+            .find(|(_, placement)| placement.allocations)
+            .and_then(|(name, _)| self.regions.get_key_value(name))
+            .map(|(name, region)| (name.as_str(), region))
+    }
+
+    pub fn find_region(&self, address: usize) -> Option<(&str, &Region)> {
+        self.regions
+            .iter()
+            .find(|(_, region)| region.contains(address))
+            .map(|(name, region)| (name.as_str(), region))
+    }
 }
 
 #[derive(serde_derive::Deserialize, Debug)]
@@ -58,10 +103,182 @@ pub struct Symbol {
     pub tags: Vec<String>,
 }
 
+#[derive(serde_derive::Deserialize, Debug)]
+pub struct Region {
+    pub start: usize,
+    pub length: usize,
+    #[serde(default)]
+    pub attributes: Vec<RegionAttribute>,
+}
+
+impl Region {
+    pub fn end(&self) -> usize {
+        self.start + self.length
+    }
+
+    pub fn contains(&self, address: usize) -> bool {
+        (self.start..self.end()).contains(&address)
+    }
+
+    pub fn has(&self, attribute: RegionAttribute) -> bool {
+        self.attributes.contains(&attribute)
+    }
+}
+
+#[derive(serde_derive::Deserialize, Debug)]
+pub struct Placement {
+    #[serde(default)]
+    pub sections: Vec<String>,
+    #[serde(default)]
+    pub allocations: bool,
+}
+
+#[derive(serde_derive::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RegionAttribute {
+    ReadOnly,
+    Uninitialized,
+    Io,
+}
+
+pub type Charmap = HashMap<char, u16>;
+
+// ranges of memory taken so far by starting address, with their ending address and owner,
+// shared by the assembler and the linker
+#[derive(Debug)]
+pub struct Occupied<T>(BTreeMap<usize, (usize, T)>);
+
+#[derive(Debug)]
+pub enum OccupyError<T> {
+    AddressOverflow,
+    UnmappedAddress,
+    RegionOverflow(String),
+    InvalidRegion(String, RegionAttribute),
+    // first overlapping address, and the owner of the range it belongs to
+    Overlap(usize, T),
+}
+
+impl<T> Default for Occupied<T> {
+    fn default() -> Self {
+        Self(BTreeMap::new())
+    }
+}
+
+impl<T: Clone> Occupied<T> {
+    // the range must fit in the `size` words of memory and, when there are regions, in a
+    // single region without any of the `forbidden` attributes
+    pub fn occupy(
+        &mut self,
+        is: &InstructionSet,
+        address: usize,
+        len: usize,
+        size: usize,
+        forbidden: &[RegionAttribute],
+        owner: T,
+    ) -> Result<usize, OccupyError<T>> {
+        let end = address + len;
+
+        if end > size {
+            return Err(OccupyError::AddressOverflow);
+        }
+
+        if len == 0 {
+            return Ok(end);
+        }
+
+        // without a memory map every address is available
+        if !is.regions.is_empty() {
+            let Some((name, region)) = is.find_region(address) else {
+                return Err(OccupyError::UnmappedAddress);
+            };
+
+            if end > region.end() {
+                return Err(OccupyError::RegionOverflow(name.to_owned()));
+            }
+
+            if let Some(attribute) = forbidden.iter().find(|attribute| region.has(**attribute)) {
+                return Err(OccupyError::InvalidRegion(name.to_owned(), *attribute));
+            }
+        }
+
+        // ranges never overlap each other, so only the closest one needs to be checked
+        if let Some((start, (previous_end, previous))) = self.0.range(..end).next_back()
+            && *previous_end > address
+        {
+            return Err(OccupyError::Overlap(address.max(*start), previous.clone()));
+        }
+
+        self.0.insert(address, (end, owner));
+
+        Ok(end)
+    }
+}
+
 impl FromStr for InstructionSet {
     type Err = toml::de::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        toml::from_str::<InstructionSet>(s)
+        let is = toml::from_str::<InstructionSet>(s)?;
+
+        // bounds are exported as `__<region>_top`, the last address of the region
+        if let Some(region) = is
+            .regions
+            .keys()
+            .find(|region| is.regions[*region].length == 0)
+        {
+            return Err(Self::Err::custom(format!("region `{region}` is empty")));
+        }
+
+        // addresses belong to a single region, see `find_region`
+        let mut regions: Vec<_> = is.regions.iter().collect();
+
+        regions.sort_by_key(|(name, region)| (region.start, *name));
+
+        if let Some([(first, _), (second, _)]) = regions
+            .windows(2)
+            .find(|pair| pair[0].1.end() > pair[1].1.start)
+        {
+            return Err(Self::Err::custom(format!(
+                "regions `{first}` and `{second}` overlap"
+            )));
+        }
+
+        // sections and allocations are placed in a single region, see `find_placement`
+        let mut placed = HashSet::new();
+
+        if let Some(section) = is
+            .placement
+            .values()
+            .flat_map(|placement| &placement.sections)
+            .find(|section| !placed.insert(*section))
+        {
+            return Err(Self::Err::custom(format!(
+                "section `{section}` is placed in more than one region"
+            )));
+        }
+
+        if is
+            .placement
+            .values()
+            .filter(|placement| placement.allocations)
+            .count()
+            > 1
+        {
+            return Err(Self::Err::custom(
+                "allocations are placed in more than one region",
+            ));
+        }
+
+        if let Some(region) = is
+            .placement
+            .keys()
+            .find(|region| !is.regions.contains_key(*region))
+        {
+            return Err(Self::Err::custom(format!(
+                "placement refers to unknown region `{region}`"
+            )));
+        }
+
+        Ok(is)
     }
 }
@@ -1,10 +1,19 @@
-use assembler::assemble;
-use clap::{Parser, value_parser};
+use std::{fmt::Display, path::PathBuf, str::FromStr};
+
+use assembler::{Options, archive::Archive, assemble_with, link_with, parse_define, parse_symbols};
+use clap::{Parser, Subcommand, value_parser};
 use clio::{Input, Output};
 
 #[derive(Parser)]
-#[command(name = "Assembler", about = "Assembler")]
+#[command(
+    name = "Assembler",
+    about = "Assembler",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
     #[arg(
         short,
         long,
@@ -13,7 +22,7 @@ struct Cli {
         required = true,
         value_parser = value_parser!(Input).exists().is_file()
     )]
-    input: Input,
+    input: Option<Input>,
     #[arg(
         short,
         long,
@@ -30,12 +39,150 @@ struct Cli {
         required = true,
         value_parser = value_parser!(Input).exists().is_file()
     )]
-    syntax: Input,
+    syntax: Option<Input>,
+    #[arg(
+        short = 'I',
+        long = "include",
+        value_name = "directory",
+        help = "Directory searched for included files, can be repeated"
+    )]
+    include_paths: Vec<PathBuf>,
+    #[arg(
+        short = 'D',
+        long = "define",
+        value_name = "NAME=value",
+        help = "Constant available to the sources, defined as 1 when no value is given, can be repeated",
+        value_parser = parse_define
+    )]
+    defines: Vec<(String, usize)>,
+    #[arg(
+        short = 'c',
+        long = "object",
+        help = "Outputs a relocatable object, to be combined with others by `link`"
+    )]
+    relocatable: bool,
+    #[arg(
+        long = "symbols",
+        value_name = "symbol file path",
+        help = "Symbols of a previous build, e.g. a fixed ROM, available to the sources as labels, can be repeated",
+        value_parser = value_parser!(Input).exists().is_file()
+    )]
+    symbols: Vec<Input>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Links relocatable objects into an image")]
+    Link {
+        #[arg(
+            value_name = "object file path",
+            help = "Object files, sections with the same name are merged in the given order",
+            required = true,
+            value_parser = value_parser!(Input).exists().is_file()
+        )]
+        objects: Vec<Input>,
+        #[arg(
+            short = 'l',
+            long = "library",
+            value_name = "archive file path",
+            help = "Archive whose members are linked when they declare labels the objects need, can be repeated",
+            value_parser = value_parser!(Input).exists().is_file()
+        )]
+        archives: Vec<Input>,
+        #[arg(
+            short,
+            long,
+            value_name = "output file path",
+            help = "Output file, if not provided, stdout will be used",
+            default_value = "-"
+        )]
+        output: Output,
+        #[arg(
+            short,
+            long,
+            value_name = "syntax file path",
+            help = "Syntax file",
+            required = true,
+            value_parser = value_parser!(Input).exists().is_file()
+        )]
+        syntax: Input,
+    },
+    #[command(about = "Bundles relocatable objects into an archive, to be linked with `link -l`")]
+    Archive {
+        #[arg(
+            value_name = "object file path",
+            help = "Object files",
+            required = true,
+            value_parser = value_parser!(Input).exists().is_file()
+        )]
+        objects: Vec<Input>,
+        #[arg(
+            short,
+            long,
+            value_name = "output file path",
+            help = "Output file, if not provided, stdout will be used",
+            default_value = "-"
+        )]
+        output: Output,
+    },
+}
+
+// reads every file, e.g. objects and archives
+fn read_all<T>(inputs: &[Input]) -> Result<Vec<T>, String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    inputs
+        .iter()
+        .map(|input| {
+            std::fs::read_to_string(input.path().path())
+                .map_err(|err| err.to_string())
+                .and_then(|text| T::from_str(&text).map_err(|err| err.to_string()))
+        })
+        .collect()
 }
 
 fn main() {
     let mut cli = Cli::parse();
 
+    match cli.command {
+        Some(Command::Link {
+            objects,
+            archives,
+            mut output,
+            syntax,
+        }) => {
+            let objects = read_all(&objects).unwrap();
+            let archives = read_all(&archives).unwrap();
+            let syntax = std::fs::read_to_string(syntax.path().path()).unwrap();
+
+            link_with(&objects, &archives, syntax)
+                .map(|assembly| {
+                    let mut output_writer = output.lock();
+                    output_writer.write(assembly.mif().as_bytes()).unwrap();
+                })
+                .unwrap();
+
+            return;
+        }
+        Some(Command::Archive {
+            objects,
+            mut output,
+        }) => {
+            let archive = Archive::new(read_all(&objects).unwrap());
+
+            let mut output_writer = output.lock();
+            output_writer.write(archive.to_string().as_bytes()).unwrap();
+
+            return;
+        }
+        None => {}
+    }
+
+    // required when no subcommand is given
+    let (input, syntax) = (cli.input.unwrap(), cli.syntax.unwrap());
+
     // TODO: Implement reading from stdin
     // let mut buffer = Vec::new();
 
@@ -49,10 +196,30 @@ fn main() {
     // fs.write("synthax.toml", &buffer).unwrap();
     // buffer.clear();
 
-    assemble(cli.input.path().path(), cli.syntax.path().path())
+    let options = Options {
+        include_paths: cli.include_paths,
+        defines: cli.defines.into_iter().collect(),
+        relocatable: cli.relocatable,
+        symbols: cli
+            .symbols
+            .iter()
+            .flat_map(|symbols| {
+                let text = std::fs::read_to_string(symbols.path().path()).unwrap();
+
+                parse_symbols(&text).unwrap()
+            })
+            .collect(),
+    };
+
+    assemble_with(input.path().path(), syntax.path().path(), &options)
         .map(|assembly| {
+            let output = match assembly.object() {
+                Some(object) => object.to_string(),
+                None => assembly.mif(),
+            };
+
             let mut output_writer = cli.output.lock();
-            output_writer.write(assembly.mif().as_bytes()).unwrap();
+            output_writer.write(output.as_bytes()).unwrap();
         })
         .unwrap();
 }
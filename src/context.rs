@@ -1,37 +1,360 @@
-use std::{collections::HashMap, error::Error, fmt::Display};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt::Display,
+};
 
 pub use crate::cis::*;
+use crate::object::Relocation;
 
 #[derive(Debug)]
-pub struct Context<'is, 'a> {
+pub struct Context<'is> {
     pub is: &'is InstructionSet,
     pub labels: HashMap<String, Option<usize>>,
+    // numeric labels with each name, in order of declaration
+    pub numeric_labels: HashMap<String, Vec<NumericLabel>>,
+    // constants defined outside of the sources, e.g. with `-D`
+    pub constants: HashMap<String, usize>,
+    // source file each label was declared in
+    pub label_files: HashMap<String, usize>,
+    // labels of a previous build, see `Options::symbols`
+    pub imported: HashSet<String>,
     pub allocations: HashMap<String, usize>,
     pub address: usize,
-    pub path: Vec<&'a str>,
+    pub end: usize,
+    // scope of local labels, the last label declared at each level
+    pub path: Vec<String>,
     pub allocation_offset: Option<usize>,
-    pub counter: u16,
+    // passes left before unresolved references are errors, see `reduce`
+    pub counter: usize,
+    pub charmap: Option<&'is Charmap>,
+    pub region: Option<String>,
+    pub region_cursors: HashMap<String, usize>,
+    // `anchor` each region continues from
+    pub region_anchors: HashMap<String, Option<usize>>,
+    pub section: Option<String>,
+    pub conditions: Vec<Condition>,
+    // set once every label was declared, after the first pass
+    pub declared: bool,
+    // set when assembling a relocatable object, see `Object`
+    pub relocatable: bool,
+    // relocatable section each label belongs to, labels with a fixed address are not listed
+    pub label_sections: HashMap<String, String>,
+    // sections given a fixed address, which are not relocated
+    pub absolute_sections: HashSet<String>,
+    // first fixed address given to each section, where it starts
+    pub section_addresses: HashMap<String, usize>,
+    // regions given to relocatable sections, placed once linked
+    pub section_regions: HashMap<String, String>,
+    pub relocations: Vec<Relocation>,
+    // labels visible to other objects, and labels expected from them, see `link`
+    pub globals: HashSet<String>,
+    pub externs: HashSet<String>,
+    // size or position the current address is relative to, when it was not known in the
+    // first pass, cleared at every origin, see `Macro::affects_layout`
+    pub anchor: Option<usize>,
+    pub anchors: Vec<Anchor>,
+    // labels declared at an address relative to an anchor, with the anchor, until it is
+    // resolved, see `lib::resolve`
+    pub provisional: HashMap<String, usize>,
+}
+
+// size or position the first pass could not reduce, the addresses after it are counted as if
+// it was empty, and moved once it is resolved
+#[derive(Debug, Clone)]
+pub struct Anchor {
+    // anchor the start is relative to
+    pub previous: Option<usize>,
+    // first pass address of the size or position, and the furthest address reached after it
+    pub start: usize,
+    pub last: usize,
+    // address right after it, once resolved
+    pub end: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NumericLabel {
+    pub address: usize,
+    // relocatable section the label belongs to
+    pub section: Option<String>,
+    // anchor the address is relative to, none once known, see `Context::provisional`
+    pub anchor: Option<usize>,
+}
+
+// state of an open conditional block, `taken` is set once any of its branches was assembled
+#[derive(Debug, Clone, Copy)]
+pub struct Condition {
+    pub active: bool,
+    pub taken: bool,
 }
 
 #[derive(Debug)]
 pub enum LabelError {
     InvalidLabel,
     Unavailable,
+    // taken by a label of a previous build
+    Imported,
 }
 
-impl<'is, 'a> Context<'is, 'a> {
-    pub fn new(is: &'is InstructionSet, max_iter: u16) -> Self {
+#[derive(Debug)]
+pub enum CharmapError {
+    UnknownCharmap,
+    Unencodable(u32),
+}
+
+impl<'is> Context<'is> {
+    pub fn new(is: &'is InstructionSet) -> Self {
+        // region bounds are exported as `__<region>_base` and `__<region>_top`
+        let labels = is
+            .regions
+            .iter()
+            .flat_map(|(name, region)| {
+                [
+                    (format!("__{name}_base"), Some(region.start)),
+                    (format!("__{name}_top"), Some(region.end() - 1)),
+                ]
+            })
+            .collect();
+
         Self {
             is,
-            labels: HashMap::new(),
+            labels,
+            numeric_labels: HashMap::new(),
+            constants: HashMap::new(),
+            label_files: HashMap::new(),
+            imported: HashSet::new(),
             allocations: HashMap::new(),
             address: 0,
+            end: 0,
             path: Vec::new(),
             allocation_offset: None,
-            counter: max_iter,
+            counter: 1,
+            charmap: None,
+            region: None,
+            region_cursors: HashMap::new(),
+            region_anchors: HashMap::new(),
+            section: None,
+            conditions: Vec::new(),
+            declared: false,
+            relocatable: false,
+            label_sections: HashMap::new(),
+            absolute_sections: HashSet::new(),
+            section_addresses: HashMap::new(),
+            section_regions: HashMap::new(),
+            relocations: Vec::new(),
+            globals: HashSet::new(),
+            externs: HashSet::new(),
+            anchor: None,
+            anchors: Vec::new(),
+            provisional: HashMap::new(),
+        }
+    }
+
+    // resets the location counters before every reduction pass
+    pub fn rewind(&mut self) {
+        self.address = 0;
+        self.region = None;
+        self.region_cursors.clear();
+        self.region_anchors.clear();
+        self.anchor = None;
+    }
+
+    // returns the address the region continues from
+    pub fn enter_region(&mut self, name: &str) -> usize {
+        self.leave_region();
+
+        let start = self.is.get_region(name).map_or(0, |region| region.start);
+
+        self.region = Some(name.to_owned());
+        self.anchor = self.region_anchors.get(name).copied().flatten();
+        self.region_cursors.get(name).copied().unwrap_or(start)
+    }
+
+    pub fn leave_region(&mut self) {
+        if let Some(region) = self.region.take() {
+            self.region_anchors.insert(region.clone(), self.anchor);
+            self.region_cursors.insert(region, self.address);
+        }
+    }
+
+    // returns the address the section starts at, when it is not placed right after the
+    // previous one, section bounds are exported as `__<section>_start` and `__<section>_end`
+    pub fn enter_section(&mut self, name: &str, address: Option<usize>) -> Option<usize> {
+        // sections of relocatable objects start at 0, and are placed and bounded once linked
+        if self.relocatable {
+            if address.is_some() {
+                self.absolute_sections.insert(name.to_owned());
+            }
+
+            self.section = Some(name.to_owned());
+
+            return address;
+        }
+
+        // sections are entered once without an address before their first directive, see
+        // `group_sections`, and again in every pass
+        let first = address.is_some() && !self.section_addresses.contains_key(name);
+
+        if let Some(address) = address.filter(|_| first) {
+            self.section_addresses.insert(name.to_owned(), address);
+        }
+
+        let start = format!("__{name}_start");
+
+        // entering the section it is already in only moves to an address given to it, the
+        // fixed address is where it starts, not where it continues
+        if self.section.as_deref() == Some(name) {
+            if first {
+                self.labels.insert(start.clone(), address);
+                self.provisional.remove(&start);
+            }
+
+            return address;
+        }
+
+        let address = address.or_else(|| self.section_addresses.get(name).copied());
+
+        self.leave_section();
+
+        let is = self.is;
+        let placed = address.or_else(|| match is.find_placement(name) {
+            Some((region, _)) => Some(self.enter_region(region)),
+            None => {
+                self.leave_region();
+                None
+            }
+        });
+
+        self.labels
+            .insert(start.clone(), Some(placed.unwrap_or(self.address)));
+
+        // a fixed address is always known
+        if address.is_none() {
+            self.settle(&start);
+        } else {
+            self.anchor = None;
+            self.provisional.remove(&start);
+        }
+
+        self.section = Some(name.to_owned());
+
+        placed
+    }
+
+    pub fn leave_section(&mut self) {
+        if let Some(section) = self.section.take()
+            && !self.relocatable
+        {
+            let end = format!("__{section}_end");
+
+            self.labels.insert(end.clone(), Some(self.address));
+            self.settle(&end);
+        }
+    }
+
+    // allocations go after the sections of the region marked for them, or after everything
+    pub fn allocation_start(&self) -> usize {
+        match self.is.allocation_region() {
+            Some((name, region)) => self
+                .region_cursors
+                .get(name)
+                .copied()
+                .unwrap_or(region.start),
+            None => self.end,
+        }
+    }
+
+    // a label declared while the address depends on an unknown size is provisional, until the
+    // size is resolved
+    pub fn settle(&mut self, label: &str) {
+        match self.anchor {
+            Some(anchor) => self.provisional.insert(label.to_owned(), anchor),
+            None => self.provisional.remove(label),
+        };
+    }
+
+    // every label has its final address
+    pub fn is_settled(&self) -> bool {
+        self.provisional.is_empty()
+            && self
+                .numeric_labels
+                .values()
+                .flatten()
+                .all(|label| label.anchor.is_none())
+    }
+
+    // the addresses after a size or position the first pass could not reduce are relative to
+    // it, returns its index
+    pub fn push_anchor(&mut self) -> usize {
+        self.anchors.push(Anchor {
+            previous: self.anchor,
+            start: self.address,
+            last: self.address,
+            end: None,
+        });
+        self.anchor = Some(self.anchors.len() - 1);
+
+        self.anchors.len() - 1
+    }
+
+    // final address of a first pass address relative to the anchor, once it is resolved
+    pub fn resolved(&self, anchor: Option<usize>, address: usize) -> Option<usize> {
+        match anchor {
+            Some(anchor) => {
+                let anchor = &self.anchors[anchor];
+
+                Some(anchor.end? + (address - anchor.start))
+            }
+            None => Some(address),
         }
     }
 
+    pub fn is_relocatable(&self, section: &str) -> bool {
+        self.relocatable && !self.absolute_sections.contains(section)
+    }
+
+    // the current section, when its addresses are relocated
+    pub fn relocatable_section(&self) -> Option<String> {
+        self.section
+            .clone()
+            .filter(|section| self.is_relocatable(section))
+    }
+
+    // labels left to the linker, once every label of the object was declared
+    pub fn is_external(&self, label: &str) -> bool {
+        self.relocatable
+            && self.declared
+            && (self.externs.contains(label) || self.labels.contains_key(label))
+            && !self.provisional.contains_key(label)
+    }
+
+    pub fn set_charmap(&mut self, name: &str) -> Result<(), CharmapError> {
+        self.charmap = Some(
+            self.is
+                .get_charmap(name)
+                .ok_or(CharmapError::UnknownCharmap)?,
+        );
+
+        Ok(())
+    }
+
+    // characters missing from the charmap are emitted as their code point
+    pub fn encode(&self, chars: &[u32]) -> Result<Box<[u16]>, CharmapError> {
+        chars
+            .iter()
+            .map(|&chr| {
+                let mapped = char::from_u32(chr)
+                    .zip(self.charmap)
+                    .and_then(|(chr, charmap)| charmap.get(&chr));
+
+                match mapped {
+                    Some(value) => Ok(*value),
+                    None => u16::try_from(chr).map_err(|_| CharmapError::Unencodable(chr)),
+                }
+            })
+            .collect()
+    }
+
     pub fn set_allocation_offset(&mut self, mut offset: usize) {
         for (name, size) in self.allocations.drain() {
             self.labels.insert(name, Some(offset));
@@ -41,41 +364,78 @@ impl<'is, 'a> Context<'is, 'a> {
         self.allocation_offset = Some(offset);
     }
 
+    // statements are only assembled when every enclosing condition holds
+    pub fn is_active(&self) -> bool {
+        self.conditions.iter().all(|condition| condition.active)
+    }
+
     pub fn advance(&mut self, len: usize) {
         self.address += len;
+
+        match self.anchor {
+            Some(anchor) => {
+                let anchor = &mut self.anchors[anchor];
+
+                anchor.last = anchor.last.max(self.address);
+            }
+            None => self.end = self.end.max(self.address),
+        }
     }
 
-    pub fn get_path(&self, label: &'a str) -> Result<String, LabelError> {
-        if label.starts_with('.') {
-            let levels = label.chars().take_while(|chr| chr == &'.').count();
-            if levels == self.path.len() {
-                let mut path = self.path.join(".");
+    // `.name` is scoped to the closest label declared without leading dots, `..name` to the
+    // closest one declared with a single dot and so on, other labels are global
+    pub fn get_path(&self, label: &str) -> Result<String, LabelError> {
+        let levels = label.chars().take_while(|chr| *chr == '.').count();
 
-                path.push('.');
-                path.push_str(&label[levels..]);
+        if levels == 0 {
+            return Ok(label.to_owned());
+        }
 
-                Ok(path)
-            } else {
-                Err(LabelError::InvalidLabel)
-            }
-        } else {
-            Ok(label.to_owned())
+        if levels > self.path.len() {
+            return Err(LabelError::InvalidLabel);
         }
+
+        Ok(format!(
+            "{}.{}",
+            self.path[..levels].join("."),
+            &label[levels..]
+        ))
     }
 
     pub fn register_label(
         &mut self,
         label: &str,
+        file: usize,
         preregistered: bool,
+        scoped: bool,
     ) -> Result<usize, LabelError> {
         let path = self.get_path(label)?;
-        let is_new = preregistered || !self.labels.contains_key(&path);
+        let is_new = preregistered
+            || !(self.labels.contains_key(&path) || self.constants.contains_key(&path));
 
         if is_new {
+            if scoped {
+                self.path = path.split('.').map(str::to_owned).collect();
+            }
+
+            if let Some(section) = self.relocatable_section() {
+                self.label_sections.insert(path.clone(), section);
+            }
+
+            self.label_files.insert(path.clone(), file);
+            self.settle(&path);
             self.labels.insert(path, Some(self.address));
             Ok(self.address)
         } else {
-            Err(LabelError::Unavailable)
+            Err(self.unavailable(&path))
+        }
+    }
+
+    fn unavailable(&self, path: &str) -> LabelError {
+        if self.imported.contains(path) {
+            LabelError::Imported
+        } else {
+            LabelError::Unavailable
         }
     }
 
@@ -99,7 +459,7 @@ impl<'is, 'a> Context<'is, 'a> {
             }
 
             Ok(())
-        } else if !self.labels.contains_key(&path) {
+        } else if !(self.labels.contains_key(&path) || self.constants.contains_key(&path)) {
             self.labels.insert(path.clone(), address);
 
             if let Some(size) = size {
@@ -112,7 +472,7 @@ impl<'is, 'a> Context<'is, 'a> {
 
             Ok(())
         } else {
-            Err(LabelError::Unavailable)
+            Err(self.unavailable(&path))
         }
     }
 }
@@ -124,3 +484,11 @@ impl Display for LabelError {
 }
 
 impl Error for LabelError {}
+
+impl Display for CharmapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("{self:#?}"))
+    }
+}
+
+impl Error for CharmapError {}
@@ -0,0 +1,91 @@
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind, Result},
+    path::{Component, Path, PathBuf},
+};
+
+// source of the entry, syntax, included and binary files
+pub trait FileProvider {
+    fn read(&self, path: &Path) -> Result<Vec<u8>>;
+
+    fn is_file(&self, path: &Path) -> bool;
+
+    // path used for telling files apart, e.g. when looking for include cycles
+    fn canonicalize(&self, path: &Path) -> PathBuf {
+        path.to_path_buf()
+    }
+
+    fn read_to_string(&self, path: &Path) -> Result<String> {
+        String::from_utf8(self.read(path)?).map_err(|err| Error::new(ErrorKind::InvalidData, err))
+    }
+}
+
+// reads files from the disk
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StdFileProvider;
+
+impl FileProvider for StdFileProvider {
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        std::fs::read(path)
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+
+    fn canonicalize(&self, path: &Path) -> PathBuf {
+        std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+    }
+}
+
+// keeps files in memory, for targets without a filesystem
+#[derive(Debug, Default, Clone)]
+pub struct MemoryFileProvider {
+    pub files: HashMap<PathBuf, Vec<u8>>,
+}
+
+impl MemoryFileProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, path: impl AsRef<Path>, content: impl Into<Vec<u8>>) {
+        self.files.insert(normalize(path.as_ref()), content.into());
+    }
+}
+
+impl FileProvider for MemoryFileProvider {
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        self.files
+            .get(&normalize(path))
+            .cloned()
+            .ok_or_else(|| Error::from(ErrorKind::NotFound))
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.files.contains_key(&normalize(path))
+    }
+
+    fn canonicalize(&self, path: &Path) -> PathBuf {
+        normalize(path)
+    }
+}
+
+// resolves `.` and `..` without touching the disk
+fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(result.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                result.pop();
+            }
+            component => result.push(component),
+        }
+    }
+
+    result
+}
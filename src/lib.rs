@@ -1,59 +1,648 @@
-use std::{collections::HashMap, fmt::Write, path::Path, str::FromStr};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-use asm::parse_line;
-use ast::{Reduce, ReduceError};
+use archive::Archive;
+use ast::{Compound, Dependencies, Expr, Layout, Macro, Reduce, ReduceError, Statement};
+use cis::InstructionSet;
 use context::Context;
+use fs::{FileProvider, MemoryFileProvider, StdFileProvider};
+use object::Object;
+use source::Sources;
 
+pub mod archive;
 pub mod asm;
 pub mod ast;
 pub mod cis;
 pub mod context;
+pub mod fs;
+pub mod linker;
+pub mod object;
+pub mod source;
 
-pub fn parse<'i>(ctx: &mut Context, input: &'i str) -> Result<Box<[u16]>, ReduceError<'i>> {
-    let mut result: Vec<_> = input
+#[cfg(test)]
+mod tests;
+
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    pub include_paths: Vec<PathBuf>,
+    // constants available to every source, see `parse_define`
+    pub defines: HashMap<String, usize>,
+    // assembles a relocatable object instead of an image, see `Assembly::object`
+    pub relocatable: bool,
+    // labels of a previous build, e.g. a fixed ROM, see `parse_symbols`
+    pub symbols: HashMap<String, usize>,
+}
+
+// parses `NAME=value`, or `NAME` which is defined as 1, values are written like number
+// literals, without the `#`
+pub fn parse_define(define: &str) -> Result<(String, usize), String> {
+    let (name, value) = define.split_once('=').unwrap_or((define, "1"));
+    let is_word = name.starts_with(|chr: char| chr.is_ascii_alphabetic() || chr == '_')
+        && name
+            .chars()
+            .all(|chr| chr.is_ascii_alphanumeric() || chr == '_');
+
+    if !is_word {
+        return Err(format!("invalid name `{name}`"));
+    }
+
+    match asm::parse_literal(value.trim()) {
+        Some(value) => Ok((name.to_owned(), value)),
+        None => Err(format!("invalid value `{value}`")),
+    }
+}
+
+// parses the output of `Assembly::symbols`, one `name = value` per line, values are written
+// like number literals, without the `#`
+pub fn parse_symbols(symbols: &str) -> Result<HashMap<String, usize>, String> {
+    symbols
         .lines()
-        .filter_map(|line| parse_line(line))
-        .flatten()
-        .collect();
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            let Some((name, value)) = line.split_once('=') else {
+                return Err(format!("line {}: expected `name = value`", index + 1));
+            };
+
+            match asm::parse_literal(value.trim()) {
+                Some(value) => Ok((name.trim().to_owned(), value)),
+                None => Err(format!(
+                    "line {}: invalid value `{}`",
+                    index + 1,
+                    value.trim()
+                )),
+            }
+        })
+        .collect()
+}
+
+// section and region bounds, see `Context::enter_section`
+fn is_bound(name: &str) -> bool {
+    name.strip_prefix("__").is_some_and(|name| {
+        ["_start", "_end", "_base", "_top"]
+            .iter()
+            .any(|suffix| name.ends_with(suffix))
+    })
+}
+
+pub fn parse<'i>(ctx: &mut Context, sources: &'i Sources) -> Result<Box<[u16]>, ReduceError<'i>> {
+    let result = reduce(ctx, sources)?;
+
+    let mut layout = Layout::new(ctx.is);
+
+    result
+        .iter()
+        .try_fold(0, |acc, statement| statement.copy(&mut layout, acc))?;
+
+    Ok(layout.buffer)
+}
+
+// addresses are relative to the section, and the ones only known once linked are recorded
+// as relocations, see `Options::relocatable`
+pub fn parse_object<'i>(
+    ctx: &mut Context,
+    sources: &'i Sources,
+) -> Result<Object, ReduceError<'i>> {
+    let result = reduce(ctx, sources)?;
+
+    Ok(Object::build(ctx, &result))
+}
 
-    ctx.address = 0;
+fn reduce<'i>(
+    ctx: &mut Context,
+    sources: &'i Sources,
+) -> Result<Vec<Statement<'i>>, ReduceError<'i>> {
+    let mut result = sources.statements();
+
+    check_conditionals(&result)?;
+
+    result = resolve_namespaces(result)?;
+
+    result = group_sections(ctx.is, result);
+
+    ctx.rewind();
 
     result = result
         .into_iter()
         .filter_map(|statement| statement.reduce(ctx).transpose())
         .collect::<Result<Vec<_>, _>>()?;
 
-    let alloc_offset = ctx.address;
+    ctx.leave_section();
+    ctx.leave_region();
+
+    ctx.declared = true;
+
+    resolve(ctx, &result)?;
+
+    // allocations go after every other address is known, and the ones of relocatable objects
+    // are placed once linked
+    if !ctx.relocatable && ctx.is_settled() {
+        let alloc_offset = ctx.allocation_start();
+
+        ctx.set_allocation_offset(alloc_offset);
+    }
+
+    // every address is known, references left are errors
+    ctx.counter = 0;
+    ctx.rewind();
+
+    result = result
+        .into_iter()
+        .filter_map(|statement| statement.reduce(ctx).transpose())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    ctx.leave_section();
+    ctx.leave_region();
+
+    Ok(result)
+}
+
+// provisional label, with its position among the numeric labels with the same name, or size
+// or position the first pass could not reduce, see `Context::anchors`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Node {
+    Label(String, Option<usize>),
+    Anchor(usize),
+}
+
+// sizes and positions the first pass could not reduce are visited in topological order, each
+// resolved from the labels it refers to and the anchor it starts after, then the labels
+// after it, the ones left unresolved are reported by the last pass
+fn resolve<'a>(ctx: &mut Context, statements: &[Statement<'a>]) -> Result<(), ReduceError<'a>> {
+    // anchors are pushed in the order of their statements
+    let sizes: Vec<&Macro<'a>> = statements
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::Macro(r#macro) if r#macro.anchor.is_some() => Some(r#macro),
+            _ => None,
+        })
+        .collect();
+
+    let mut done = HashSet::new();
+    let mut visiting = HashSet::new();
+    // nodes being visited, with the dependencies left to visit
+    let mut stack: Vec<(Node, Vec<Node>)> = Vec::new();
+
+    for root in (0..sizes.len()).map(Node::Anchor) {
+        if done.contains(&root) {
+            continue;
+        }
+
+        visiting.insert(root.clone());
+        stack.push((root.clone(), dependencies(ctx, &sizes, &root)));
+
+        while let Some((node, dependencies)) = stack.last_mut() {
+            match dependencies.pop() {
+                Some(dependency) if done.contains(&dependency) => {}
+                Some(dependency) if visiting.contains(&dependency) => {
+                    let start = stack
+                        .iter()
+                        .position(|(node, _)| *node == dependency)
+                        .unwrap();
+
+                    return Err(cyclic_definition(&sizes, &stack[start..]));
+                }
+                Some(dependency) => {
+                    let dependencies = self::dependencies(ctx, &sizes, &dependency);
+
+                    visiting.insert(dependency.clone());
+                    stack.push((dependency, dependencies));
+                }
+                None => {
+                    let node = node.clone();
+
+                    stack.pop();
+                    visiting.remove(&node);
+                    evaluate(ctx, &sizes, &node)?;
+                    done.insert(node);
+                }
+            }
+        }
+    }
+
+    // labels no size refers to
+    let labels: Vec<_> = ctx
+        .provisional
+        .keys()
+        .map(|name| Node::Label(name.clone(), None))
+        .chain(ctx.numeric_labels.iter().flat_map(|(name, labels)| {
+            (0..labels.len())
+                .filter(|index| labels[*index].anchor.is_some())
+                .map(|index| Node::Label(name.clone(), Some(index)))
+        }))
+        .collect();
+
+    for label in labels {
+        evaluate(ctx, &sizes, &label)?;
+    }
+
+    // the furthest address and where each region continues, to place the allocations after
+    for anchor in 0..ctx.anchors.len() {
+        if let Some(last) = ctx.resolved(Some(anchor), ctx.anchors[anchor].last) {
+            ctx.end = ctx.end.max(last);
+        }
+    }
+
+    let cursors: Vec<_> = ctx
+        .region_cursors
+        .iter()
+        .filter_map(|(region, cursor)| {
+            let anchor = ctx.region_anchors.get(region).copied().flatten();
+
+            Some((region.clone(), ctx.resolved(anchor, *cursor)?))
+        })
+        .collect();
+
+    ctx.region_cursors.extend(cursors);
+
+    Ok(())
+}
 
-    ctx.set_allocation_offset(alloc_offset);
+// an anchor depends on the provisional labels it refers to and on the anchor it starts after,
+// unless it is an origin, and a label on its anchor
+fn dependencies(ctx: &Context, sizes: &[&Macro], node: &Node) -> Vec<Node> {
+    match node {
+        Node::Anchor(anchor) => {
+            let r#macro = sizes[*anchor];
+            let previous = ctx.anchors[*anchor]
+                .previous
+                .filter(|_| r#macro.directive != "org");
 
-    loop {
-        ctx.address = 0;
+            let labels = r#macro
+                .dependencies()
+                .into_iter()
+                .filter(|(name, index)| match index {
+                    Some(index) => ctx
+                        .numeric_labels
+                        .get(*name)
+                        .and_then(|labels| labels.get(*index))
+                        .is_some_and(|label| label.anchor.is_some()),
+                    None => ctx.provisional.contains_key(*name),
+                })
+                .map(|(name, index)| Node::Label(name.to_owned(), index));
 
-        result = result
+            previous
+                .map(Node::Anchor)
+                .into_iter()
+                .chain(labels)
+                .collect()
+        }
+        Node::Label(name, Some(index)) => ctx.numeric_labels[name][*index]
+            .anchor
+            .map(Node::Anchor)
+            .into_iter()
+            .collect(),
+        Node::Label(name, None) => ctx
+            .provisional
+            .get(name)
+            .copied()
+            .map(Node::Anchor)
             .into_iter()
-            .filter_map(|statement| statement.reduce(ctx).transpose())
-            .collect::<Result<Vec<_>, _>>()?;
+            .collect(),
+    }
+}
+
+// resolves a node once its dependencies are, anchors that do not reduce to an integer are
+// left unresolved, and so are the addresses after them
+fn evaluate<'a>(
+    ctx: &mut Context,
+    sizes: &[&Macro<'a>],
+    node: &Node,
+) -> Result<(), ReduceError<'a>> {
+    match node {
+        Node::Anchor(index) => {
+            let r#macro = sizes[*index];
+            let anchor = &ctx.anchors[*index];
+            let start = ctx.resolved(anchor.previous, anchor.start);
+
+            let Expr::Integer { value, .. } = r#macro.arguments.expr_list[0].clone().reduce(ctx)?
+            else {
+                return Ok(());
+            };
 
-        if ctx.counter == 0 {
-            break;
+            ctx.anchors[*index].end = match r#macro.directive {
+                "org" => Some(value),
+                "align" if value == 0 => None,
+                "align" => start.map(|start| start.next_multiple_of(value)),
+                _ => start.map(|start| start + value),
+            };
         }
+        Node::Label(name, Some(index)) => {
+            let label = &ctx.numeric_labels[name][*index];
 
-        ctx.counter -= 1;
+            if let Some(address) = ctx.resolved(label.anchor, label.address) {
+                let label = &mut ctx.numeric_labels.get_mut(name).unwrap()[*index];
+
+                label.address = address;
+                label.anchor = None;
+            }
+        }
+        Node::Label(name, None) => {
+            let anchor = ctx.provisional.get(name).copied();
+
+            if let Some(Some(address)) = ctx.labels.get(name)
+                && let Some(address) = ctx.resolved(anchor, *address)
+            {
+                ctx.labels.insert(name.clone(), Some(address));
+                ctx.provisional.remove(name);
+            }
+        }
     }
 
-    let mut data = Box::new([0u16; 0x10000]);
+    Ok(())
+}
 
-    result
+// names the labels of a cycle, the first one repeated at its end, and points at a size in it,
+// every cycle goes through a label and a size since labels only depend on sizes
+fn cyclic_definition<'a>(sizes: &[&Macro<'a>], cycle: &[(Node, Vec<Node>)]) -> ReduceError<'a> {
+    let statement = cycle
         .iter()
-        .fold(0, |acc, statement| statement.copy(data.as_mut_slice(), acc));
+        .find_map(|(node, _)| match node {
+            Node::Anchor(anchor) => Some(sizes[*anchor].pair.clone()),
+            Node::Label(..) => None,
+        })
+        .unwrap();
+    let mut cycle: Vec<_> = cycle
+        .iter()
+        .filter_map(|(node, _)| match node {
+            Node::Label(name, _) => Some(name.clone()),
+            Node::Anchor(_) => None,
+        })
+        .collect();
+
+    cycle.push(cycle[0].clone());
+
+    ReduceError::CyclicDefinition { statement, cycle }
+}
+
+// conditional blocks must be balanced, and cannot switch sections since sections are
+// grouped before their conditions are evaluated
+fn check_conditionals<'a>(statements: &[Statement<'a>]) -> Result<(), ReduceError<'a>> {
+    // opening directive of each open block, and whether it already has an else
+    let mut open = Vec::new();
 
-    Ok(data)
+    for statement in statements {
+        let Statement::Macro(r#macro) = statement else {
+            continue;
+        };
+
+        match (r#macro.conditional(), open.last_mut()) {
+            (Some("if" | "ifdef" | "ifndef"), _) => open.push((&r#macro.pair, false)),
+            (Some("elif"), Some((_, false))) => {}
+            (Some("else"), Some((_, has_else @ false))) => *has_else = true,
+            (Some("endif"), Some(_)) => {
+                open.pop();
+            }
+            (Some(_), _) => return Err(ReduceError::UnbalancedConditional(r#macro.pair.clone())),
+            (None, Some(_)) if r#macro.section().is_some() => {
+                return Err(ReduceError::SectionInConditional(r#macro.pair.clone()));
+            }
+            (None, _) => {}
+        }
+    }
+
+    match open.pop() {
+        Some((pair, _)) => Err(ReduceError::UnbalancedConditional(pair.clone())),
+        None => Ok(()),
+    }
+}
+
+// prefixes the labels declared inside `namespace` blocks with the namespace, and points
+// references to the innermost namespace declaring them, namespaces are lexical, so this
+// happens before sections are grouped, qualified references, `video::print`, can only be
+// made to exported labels from outside of their namespace
+fn resolve_namespaces(statements: Vec<Statement>) -> Result<Vec<Statement>, ReduceError> {
+    let mut namespaces = Vec::<String>::new();
+    // opening directive of each open block
+    let mut open = Vec::new();
+    let mut declared = HashSet::new();
+    let mut exports = HashSet::new();
+
+    for statement in &statements {
+        let namespace = namespaces.last().map_or("", String::as_str);
+
+        match statement {
+            Statement::Label(label) if is_global(label.pair.as_str()) => {
+                declared.insert(qualify(namespace, label.pair.as_str()));
+            }
+            Statement::Macro(r#macro) => {
+                let arguments = &r#macro.arguments.expr_list;
+                let names = || {
+                    arguments.iter().map(|expr| match expr {
+                        Expr::LabelRef { name, .. } => Ok(*name),
+                        _ => Err(ReduceError::TypeError),
+                    })
+                };
+
+                match (r#macro.namespace(), r#macro.directive) {
+                    (Some("namespace"), _) => {
+                        r#macro
+                            .arguments
+                            .validate_argc(1)
+                            .map_err(|err| err.to_reduce_err(r#macro.pair.clone()))?;
+
+                        let name = names().next().unwrap()?;
+
+                        namespaces.push(qualify(namespace, name));
+                        open.push(&r#macro.pair);
+                    }
+                    (Some("endnamespace"), _) => {
+                        r#macro
+                            .arguments
+                            .validate_argc(0)
+                            .map_err(|err| err.to_reduce_err(r#macro.pair.clone()))?;
+
+                        namespaces.pop().ok_or_else(|| {
+                            ReduceError::UnbalancedNamespace(r#macro.pair.clone())
+                        })?;
+                        open.pop();
+                    }
+                    (Some(_), _) => {
+                        for name in names() {
+                            exports.insert(qualify(namespace, name?));
+                        }
+                    }
+                    (None, "alloc") => {
+                        if let Some(Expr::LabelRef { name, .. }) = arguments.first() {
+                            declared.insert(qualify(namespace, name));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(pair) = open.pop() {
+        return Err(ReduceError::UnbalancedNamespace(pair.clone()));
+    }
+
+    let mut result = Vec::with_capacity(statements.len());
+
+    for mut statement in statements {
+        let namespace = namespaces.last().cloned();
+        let scope = namespace.as_deref().unwrap_or("");
+
+        match &mut statement {
+            Statement::Label(label) => label.namespace = namespace,
+            Statement::Macro(r#macro) if r#macro.namespace().is_some() => {
+                match r#macro.namespace() {
+                    Some("namespace") => {
+                        let Some(Expr::LabelRef { name, .. }) = r#macro.arguments.expr_list.first()
+                        else {
+                            unreachable!()
+                        };
+
+                        namespaces.push(qualify(scope, name));
+                    }
+                    Some("endnamespace") => {
+                        namespaces.pop();
+                    }
+                    _ => {}
+                }
+
+                continue;
+            }
+            // these take names that are not labels
+            Statement::Macro(r#macro)
+                if matches!(r#macro.directive, "charmap" | "section" | "incbin") => {}
+            Statement::Macro(r#macro) => {
+                for expr in &mut r#macro.arguments.expr_list {
+                    resolve_reference(expr, scope, &declared, &exports)?;
+                }
+            }
+            Statement::Instruction(instruction) => {
+                for expr in &mut instruction.arguments.expr_list {
+                    resolve_reference(expr, scope, &declared, &exports)?;
+                }
+            }
+            _ => {}
+        }
+
+        result.push(statement);
+    }
+
+    Ok(result)
+}
+
+fn resolve_reference<'a>(
+    expr: &mut Expr<'a>,
+    namespace: &str,
+    declared: &HashSet<String>,
+    exports: &HashSet<String>,
+) -> Result<(), ReduceError<'a>> {
+    match expr {
+        Expr::Compound(Compound { lhs, rhs, .. }) => {
+            resolve_reference(lhs, namespace, declared, exports)?;
+            resolve_reference(rhs, namespace, declared, exports)
+        }
+        Expr::LabelRef { name, pair } => {
+            let label = name.split('.').next().unwrap();
+
+            // references starting with `::` are global
+            if let Some(global) = name.strip_prefix("::") {
+                *expr = Expr::ScopedRef {
+                    pair: pair.clone(),
+                    name,
+                    path: Some(global.to_owned()),
+                };
+
+                return Ok(());
+            }
+
+            if let Some((owner, _)) = label.rsplit_once("::") {
+                let is_inside = namespace == owner || namespace.starts_with(&format!("{owner}::"));
+
+                if declared.contains(label) && !is_inside && !exports.contains(label) {
+                    return Err(ReduceError::NotExported(pair.clone()));
+                }
+
+                return Ok(());
+            }
+
+            // the innermost namespace declaring the label, outer namespaces otherwise
+            let mut scope = Some(namespace).filter(|scope| !scope.is_empty());
+
+            while let Some(current) = scope {
+                if declared.contains(&qualify(current, label)) {
+                    *expr = Expr::ScopedRef {
+                        pair: pair.clone(),
+                        name,
+                        path: Some(qualify(current, name)),
+                    };
+
+                    return Ok(());
+                }
+
+                scope = current.rsplit_once("::").map(|(outer, _)| outer);
+            }
+
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn qualify(namespace: &str, name: &str) -> String {
+    match namespace {
+        "" => name.to_owned(),
+        _ => format!("{namespace}::{name}"),
+    }
+}
+
+// labels which are neither local nor numeric
+fn is_global(label: &str) -> bool {
+    !label.starts_with('.') && !label.bytes().all(|chr| chr.is_ascii_digit())
+}
+
+// moves the statements of each section together, so that every section keeps its own
+// location counter, sections listed in the placement are ordered by region and position,
+// followed by the remaining ones in order of first appearance
+fn group_sections<'a>(is: &InstructionSet, statements: Vec<Statement<'a>>) -> Vec<Statement<'a>> {
+    let mut sections = vec![("code", vec![Statement::Section("code")])];
+    let mut current = 0;
+
+    for statement in statements {
+        if let Statement::Macro(r#macro) = &statement
+            && let Some(name) = r#macro.section()
+        {
+            current = match sections.iter().position(|(section, _)| *section == name) {
+                Some(index) => index,
+                None => {
+                    sections.push((name, vec![Statement::Section(name)]));
+                    sections.len() - 1
+                }
+            };
+        }
+
+        sections[current].1.push(statement);
+    }
+
+    sections.sort_by_key(|(name, _)| {
+        let rank = is
+            .find_placement(name)
+            .map(|(region, index)| (is.regions[region].start, index));
+
+        (rank.is_none(), rank)
+    });
+
+    sections
+        .into_iter()
+        .flat_map(|(_, statements)| statements)
+        .collect()
 }
 
 pub struct Assembly {
     data: Box<[u16]>,
     symbols: HashMap<String, Option<usize>>,
+    files: Vec<PathBuf>,
+    symbol_files: HashMap<String, usize>,
+    object: Option<Object>,
 }
 
 impl Assembly {
@@ -70,6 +659,12 @@ impl Assembly {
         buffer
     }
 
+    pub fn symbol_file(&self, name: &str) -> Option<&Path> {
+        self.symbol_files
+            .get(name)
+            .map(|file| self.files[*file].as_path())
+    }
+
     pub fn binary(&self) -> Vec<u16> {
         self.data.to_vec()
     }
@@ -77,28 +672,148 @@ impl Assembly {
     pub fn mif(&self) -> String {
         mif::Mif::new(&self.data, mif::Radix::Hex, mif::Radix::Bin).to_string()
     }
+
+    // only assembled with `Options::relocatable`, the image is empty then
+    pub fn object(&self) -> Option<&Object> {
+        self.object.as_ref()
+    }
 }
 
 pub fn assemble(entry: impl AsRef<Path>, syntax: impl AsRef<Path>) -> Result<Assembly, String> {
-    let entry = std::fs::read_to_string(entry).unwrap();
-    let syntax = std::fs::read_to_string(syntax).unwrap();
+    assemble_with(entry, syntax, &Options::default())
+}
 
-    assemble_from_buf(entry, syntax)
+pub fn assemble_with(
+    entry: impl AsRef<Path>,
+    syntax: impl AsRef<Path>,
+    options: &Options,
+) -> Result<Assembly, String> {
+    assemble_from(&StdFileProvider, entry, syntax, options)
 }
 
+// reads the entry, syntax, included and binary files from the given provider
+pub fn assemble_from(
+    files: &dyn FileProvider,
+    entry: impl AsRef<Path>,
+    syntax: impl AsRef<Path>,
+    options: &Options,
+) -> Result<Assembly, String> {
+    let syntax = source::read(files, syntax.as_ref()).map_err(|err| err.to_string())?;
+    let sources = Sources::load(files, entry, &options.include_paths, &options.defines)
+        .map_err(|err| err.to_string())?;
+
+    assemble_sources(sources, syntax, options)
+}
+
+// the entry is given as text, with no files to include
 pub fn assemble_from_buf(
     input: impl AsRef<str>,
     syntax: impl AsRef<str>,
+) -> Result<Assembly, String> {
+    assemble_from_buf_with(
+        &MemoryFileProvider::new(),
+        "<input>",
+        input,
+        syntax,
+        &Options::default(),
+    )
+}
+
+// the entry is given as text, e.g. an unsaved file of an editor, its includes are looked up
+// relative to the entry path and read from the provider
+pub fn assemble_from_buf_with(
+    files: &dyn FileProvider,
+    entry: impl AsRef<Path>,
+    input: impl AsRef<str>,
+    syntax: impl AsRef<str>,
+    options: &Options,
+) -> Result<Assembly, String> {
+    let sources = Sources::from_buf(
+        files,
+        entry.as_ref(),
+        input.as_ref(),
+        &options.include_paths,
+        &options.defines,
+    )
+    .map_err(|err| err.to_string())?;
+
+    assemble_sources(sources, syntax, options)
+}
+
+fn assemble_sources(
+    sources: Sources,
+    syntax: impl AsRef<str>,
+    options: &Options,
 ) -> Result<Assembly, String> {
     let is = cis::InstructionSet::from_str(syntax.as_ref()).map_err(|err| err.to_string())?;
 
-    let (result, symbols) = {
-        let mut ctx = Context::new(&is, 100);
+    let (result, symbols, symbol_files) = {
+        let mut ctx = context(&is, options);
+
+        let result = if options.relocatable {
+            parse_object(&mut ctx, &sources).map(|object| (Box::default(), Some(object)))
+        } else {
+            parse(&mut ctx, &sources).map(|data| (data, None))
+        };
 
-        (parse(&mut ctx, input.as_ref()), ctx.labels)
+        (result, ctx.labels, ctx.label_files)
     };
 
-    let data = result.map_err(|err| err.to_string())?;
+    let (data, mut object) = result.map_err(|err| sources.describe(&err))?;
+    let files: Vec<_> = sources
+        .files
+        .into_iter()
+        .map(|source| source.path)
+        .collect();
+
+    if let Some(object) = &mut object {
+        object.files = files.clone();
+    }
+
+    Ok(Assembly {
+        data,
+        symbols,
+        files,
+        symbol_files,
+        object,
+    })
+}
+
+// context of a build with the given options
+fn context<'is>(is: &'is InstructionSet, options: &Options) -> Context<'is> {
+    let mut ctx = Context::new(is);
+
+    ctx.constants = options.defines.clone();
+
+    // bounds are defined again by this build
+    for (name, value) in options.symbols.iter().filter(|(name, _)| !is_bound(name)) {
+        ctx.imported.insert(name.clone());
+        ctx.labels.insert(name.clone(), Some(*value));
+    }
+
+    ctx.relocatable = options.relocatable;
+
+    ctx
+}
+
+// places the sections of the objects and fills in their relocations, sections with the same
+// name are merged in the given order
+pub fn link(objects: &[Object], syntax: impl AsRef<str>) -> Result<Assembly, String> {
+    link_with(objects, &[], syntax)
+}
+
+// members of the archives are only linked when they declare labels the objects need, and are
+// placed after the objects
+pub fn link_with(
+    objects: &[Object],
+    archives: &[Archive],
+    syntax: impl AsRef<str>,
+) -> Result<Assembly, String> {
+    let is = cis::InstructionSet::from_str(syntax.as_ref()).map_err(|err| err.to_string())?;
+    let objects: Vec<_> = objects
+        .iter()
+        .chain(archive::resolve(objects, archives))
+        .collect();
 
-    Ok(Assembly { data, symbols })
+    linker::link(&is, &objects).map_err(|err| err.to_string())
 }
@@ -0,0 +1,399 @@
+use std::{collections::HashMap, error::Error, fmt::Display, path::PathBuf};
+
+use crate::{
+    Assembly,
+    cis::{InstructionSet, Occupied, OccupyError, RegionAttribute},
+    object::{Chunk, ChunkKind, Object, Target},
+};
+
+#[derive(Debug)]
+pub enum LinkError {
+    // symbol referenced by an object and declared by none, `file` is the referencing one
+    Undefined {
+        symbol: String,
+        file: Option<PathBuf>,
+    },
+    Redefinition {
+        symbol: String,
+        file: Option<PathBuf>,
+    },
+    UnknownRegion {
+        section: String,
+        region: String,
+    },
+    Overlap {
+        address: usize,
+    },
+    AddressOverflow {
+        address: usize,
+    },
+    UnmappedAddress {
+        address: usize,
+    },
+    RegionOverflow {
+        address: usize,
+        region: String,
+    },
+    InvalidRegion {
+        address: usize,
+        region: String,
+        attribute: RegionAttribute,
+    },
+}
+
+// sections with the same name from every object, laid out one after another
+struct Merged<'o> {
+    name: &'o str,
+    region: Option<&'o str>,
+    length: usize,
+    alignment: usize,
+    // object of each part, with its offset in the merged section
+    parts: Vec<(usize, usize)>,
+}
+
+struct Image<'is> {
+    buffer: Box<[u16]>,
+    is: &'is InstructionSet,
+    occupied: Occupied<()>,
+}
+
+impl<'is> Image<'is> {
+    fn occupy(
+        &mut self,
+        address: usize,
+        len: usize,
+        forbidden: &[RegionAttribute],
+    ) -> Result<usize, LinkError> {
+        let size = self.buffer.len();
+
+        self.occupied
+            .occupy(self.is, address, len, size, forbidden, ())
+            .map_err(|err| LinkError::from_occupy_err(err, address))
+    }
+
+    fn copy(&mut self, chunk: &Chunk, base: usize) -> Result<(), LinkError> {
+        let address = base + chunk.offset;
+        let end = self.occupy(address, chunk.length, chunk.kind.forbidden())?;
+
+        if chunk.kind != ChunkKind::Reserve {
+            self.buffer[address..end].copy_from_slice(&chunk.data);
+        }
+
+        Ok(())
+    }
+}
+
+// sections are merged by name and placed like the sections of a single source, see
+// `group_sections` and `Context::enter_section`, allocations go after them
+pub fn link(is: &InstructionSet, objects: &[&Object]) -> Result<Assembly, LinkError> {
+    let mut merged = vec![Merged {
+        name: "code",
+        region: None,
+        length: 0,
+        alignment: 1,
+        parts: Vec::new(),
+    }];
+
+    for (object_index, object) in objects.iter().enumerate() {
+        for section in &object.sections {
+            let index = match merged.iter().position(|merged| merged.name == section.name) {
+                Some(index) => index,
+                None => {
+                    merged.push(Merged {
+                        name: &section.name,
+                        region: None,
+                        length: 0,
+                        alignment: 1,
+                        parts: Vec::new(),
+                    });
+                    merged.len() - 1
+                }
+            };
+
+            let merged = &mut merged[index];
+            let offset = merged.length.next_multiple_of(section.alignment);
+
+            merged.region = merged.region.or(section.region.as_deref());
+            merged.length = offset + section.length;
+            merged.alignment = merged.alignment.max(section.alignment);
+            merged.parts.push((object_index, offset));
+        }
+    }
+
+    merged.sort_by_key(|section| {
+        let rank = is
+            .find_placement(section.name)
+            .map(|(region, index)| (is.regions[region].start, index));
+
+        (rank.is_none(), rank)
+    });
+
+    // region bounds are exported like in `Context::new`
+    let mut symbols: HashMap<String, Option<usize>> = is
+        .regions
+        .iter()
+        .flat_map(|(name, region)| {
+            [
+                (format!("__{name}_base"), Some(region.start)),
+                (format!("__{name}_top"), Some(region.end() - 1)),
+            ]
+        })
+        .collect();
+    // address of each section of each object
+    let mut bases = HashMap::new();
+    let mut region_cursors = HashMap::new();
+    let mut address = 0;
+    let mut end = 0;
+
+    for section in &merged {
+        let region = is
+            .find_placement(section.name)
+            .map(|(region, _)| region)
+            .or(section.region);
+
+        let start = match region {
+            Some(name) => {
+                let Some(region) = is.get_region(name) else {
+                    return Err(LinkError::UnknownRegion {
+                        section: section.name.to_owned(),
+                        region: name.to_owned(),
+                    });
+                };
+
+                *region_cursors.get(name).unwrap_or(&region.start)
+            }
+            None => address,
+        }
+        .next_multiple_of(section.alignment);
+
+        address = start + section.length;
+        end = usize::max(end, address);
+
+        if let Some(region) = region {
+            region_cursors.insert(region, address);
+        }
+
+        symbols.insert(format!("__{}_start", section.name), Some(start));
+        symbols.insert(format!("__{}_end", section.name), Some(address));
+
+        for (object, offset) in &section.parts {
+            bases.insert((*object, section.name), start + offset);
+        }
+    }
+
+    let mut files = Vec::new();
+    let mut symbol_files = HashMap::new();
+    let mut allocations = Vec::new();
+    // every symbol of each object, only the global ones are listed in `symbols` too
+    let mut locals = vec![HashMap::new(); objects.len()];
+
+    for (object_index, object) in objects.iter().enumerate() {
+        let first_file = files.len();
+
+        files.extend(object.files.iter().cloned());
+
+        for symbol in &object.symbols {
+            let base = match &symbol.section {
+                Some(section) => bases[&(object_index, section.as_str())],
+                None => 0,
+            };
+
+            locals[object_index].insert(symbol.name.as_str(), base + symbol.value);
+
+            if !symbol.global {
+                continue;
+            }
+
+            if symbols.contains_key(&symbol.name) {
+                return Err(LinkError::Redefinition {
+                    symbol: symbol.name.clone(),
+                    file: symbol.file.map(|file| object.files[file].clone()),
+                });
+            }
+
+            symbols.insert(symbol.name.clone(), Some(base + symbol.value));
+
+            if let Some(file) = symbol.file {
+                symbol_files.insert(symbol.name.clone(), first_file + file);
+            }
+        }
+
+        allocations.extend(
+            object
+                .allocations
+                .iter()
+                .map(|allocation| (object_index, allocation)),
+        );
+    }
+
+    let mut image = Image {
+        buffer: Box::new([0u16; 0x10000]),
+        is,
+        occupied: Occupied::default(),
+    };
+
+    // allocations go after the sections of the region marked for them, or after everything
+    let mut offset = match is.allocation_region() {
+        Some((name, region)) => *region_cursors.get(name).unwrap_or(&region.start),
+        None => end,
+    };
+
+    for (object_index, allocation) in allocations {
+        locals[object_index].insert(allocation.name.as_str(), offset);
+
+        if allocation.global {
+            if symbols.contains_key(&allocation.name) {
+                return Err(LinkError::Redefinition {
+                    symbol: allocation.name.clone(),
+                    file: objects[object_index].files.first().cloned(),
+                });
+            }
+
+            symbols.insert(allocation.name.clone(), Some(offset));
+        }
+
+        offset = image.occupy(offset, allocation.length, ChunkKind::Reserve.forbidden())?;
+    }
+
+    for (object_index, object) in objects.iter().enumerate() {
+        for section in &object.sections {
+            let base = bases[&(object_index, section.name.as_str())];
+
+            for chunk in &section.chunks {
+                image.copy(chunk, base)?;
+            }
+        }
+
+        for chunk in &object.absolute {
+            image.copy(chunk, 0)?;
+        }
+    }
+
+    for (object_index, object) in objects.iter().enumerate() {
+        for relocation in &object.relocations {
+            let base = |section: &str| bases.get(&(object_index, section)).copied();
+            let target = match &relocation.target {
+                Target::Section(section) => base(section),
+                Target::Symbol(symbol) => locals[object_index]
+                    .get(symbol.as_str())
+                    .copied()
+                    .or_else(|| symbols.get(symbol).copied().flatten()),
+            };
+
+            let Some(target) = target else {
+                let (Target::Section(symbol) | Target::Symbol(symbol)) = &relocation.target;
+
+                return Err(LinkError::Undefined {
+                    symbol: symbol.clone(),
+                    file: relocation
+                        .file
+                        .and_then(|file| object.files.get(file).cloned()),
+                });
+            };
+
+            let address = match &relocation.section {
+                Some(section) => base(section).unwrap_or(0),
+                None => 0,
+            } + relocation.offset;
+
+            if let Some(data) = image
+                .buffer
+                .get_mut(address..address + relocation.kind.words)
+            {
+                relocation
+                    .kind
+                    .apply(data, target.wrapping_add(relocation.addend));
+            }
+        }
+    }
+
+    Ok(Assembly {
+        data: image.buffer,
+        symbols,
+        files,
+        symbol_files,
+        object: None,
+    })
+}
+
+impl LinkError {
+    fn from_occupy_err(err: OccupyError<()>, address: usize) -> Self {
+        match err {
+            OccupyError::AddressOverflow => Self::AddressOverflow { address },
+            OccupyError::UnmappedAddress => Self::UnmappedAddress { address },
+            OccupyError::RegionOverflow(region) => Self::RegionOverflow { address, region },
+            OccupyError::InvalidRegion(region, attribute) => Self::InvalidRegion {
+                address,
+                region,
+                attribute,
+            },
+            OccupyError::Overlap(address, ()) => Self::Overlap { address },
+        }
+    }
+}
+
+impl Display for LinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("{self:#?}"))
+    }
+}
+
+impl Error for LinkError {}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, str::FromStr};
+
+    use super::{LinkError, link};
+    use crate::{
+        cis::InstructionSet,
+        tests::{SYNTAX, object},
+    };
+
+    fn link_sources(sources: &[&str]) -> Result<Vec<u16>, LinkError> {
+        let is = InstructionSet::from_str(SYNTAX).unwrap();
+        let objects: Vec<_> = sources.iter().map(|source| object(source)).collect();
+
+        link(&is, &objects.iter().collect::<Vec<_>>()).map(|assembly| assembly.binary())
+    }
+
+    #[test]
+    fn externs() {
+        let binary = link_sources(&[
+            "extern print, data_end\nstart: jmp print\nword data_end",
+            "global print, data_end\nprint: halt\nsection data\nword #1\ndata_end:",
+        ])
+        .unwrap();
+
+        assert_eq!(binary[..5], [0x800, 3, 5, 0x3c00, 1]);
+    }
+
+    #[test]
+    fn missing_externs() {
+        let result = link_sources(&["halt", "extern nope\njmp nope"]);
+
+        assert!(matches!(
+            result,
+            Err(LinkError::Undefined { symbol, file: Some(file) })
+                if symbol == "nope" && file == Path::new("main.asm")
+        ));
+    }
+
+    #[test]
+    fn private_names() {
+        let binary = link_sources(&["loop: jmp loop", "loop: jmp loop"]).unwrap();
+
+        assert_eq!(binary[..4], [0x800, 0, 0x800, 2]);
+
+        let result = link_sources(&["global loop\nloop: halt", "global loop\nloop: halt"]);
+
+        assert!(matches!(result, Err(LinkError::Redefinition { symbol, .. }) if symbol == "loop"));
+    }
+
+    #[test]
+    fn overlaps() {
+        let result = link_sources(&["static #0x10, #1", "static #0x10, #2"]);
+
+        assert!(matches!(result, Err(LinkError::Overlap { address: 0x10 })));
+    }
+}
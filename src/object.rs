@@ -0,0 +1,363 @@
+use std::{collections::HashSet, fmt::Display, path::PathBuf, str::FromStr};
+
+use crate::{
+    ast::Statement,
+    cis::Argument,
+    context::{Context, RegionAttribute},
+};
+
+// relocatable object, assembled with `Options::relocatable` and combined into an image by
+// `link`, sections start at offset 0 and are only placed once linked, everything given a
+// fixed address is kept in `absolute`
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Debug, Clone, Default)]
+pub struct Object {
+    #[serde(default)]
+    pub files: Vec<PathBuf>,
+    #[serde(default)]
+    pub sections: Vec<Section>,
+    #[serde(default)]
+    pub absolute: Vec<Chunk>,
+    #[serde(default)]
+    pub symbols: Vec<Symbol>,
+    #[serde(default)]
+    pub relocations: Vec<Relocation>,
+    #[serde(default)]
+    pub allocations: Vec<Allocation>,
+}
+
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Debug, Clone)]
+pub struct Section {
+    pub name: String,
+    // region given with `section name, region`, placement of the syntax is used otherwise
+    pub region: Option<String>,
+    pub length: usize,
+    // largest `align` used in the section, kept when sections of different objects are merged
+    pub alignment: usize,
+    pub chunks: Vec<Chunk>,
+}
+
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Debug, Clone)]
+pub struct Chunk {
+    pub offset: usize,
+    pub kind: ChunkKind,
+    // reservations have no data
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub data: Vec<u16>,
+    pub length: usize,
+}
+
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChunkKind {
+    Data,
+    Variable,
+    Reserve,
+}
+
+impl ChunkKind {
+    // region attributes the chunk cannot be placed in, see `Statement::copy`
+    pub fn forbidden(&self) -> &'static [RegionAttribute] {
+        use RegionAttribute::*;
+
+        match self {
+            Self::Data => &[Uninitialized, Io],
+            Self::Variable => &[ReadOnly, Uninitialized, Io],
+            Self::Reserve => &[ReadOnly],
+        }
+    }
+}
+
+// label declared in the object, `section` is none for labels with a fixed address, only
+// labels listed with `global` are visible to other objects
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub section: Option<String>,
+    pub value: usize,
+    #[serde(default)]
+    pub global: bool,
+    // index of the source file it was declared in, in `Object::files`
+    pub file: Option<usize>,
+}
+
+// word or instruction field to be filled with the address of `target + addend` once linked,
+// `section` is none for relocations in `Object::absolute`
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Debug, Clone)]
+pub struct Relocation {
+    pub section: Option<String>,
+    pub offset: usize,
+    pub target: Target,
+    pub addend: usize,
+    pub kind: RelocationKind,
+    // index of the source file it comes from, in `Object::files`
+    pub file: Option<usize>,
+}
+
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Target {
+    // start of a section of the same object
+    Section(String),
+    // symbol of any object, or an allocation
+    Symbol(String),
+}
+
+// bits of the relocated words holding the address, taken from the instruction argument
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Debug, Clone, Copy)]
+pub struct RelocationKind {
+    pub words: usize,
+    pub offset: usize,
+    pub length: usize,
+}
+
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Debug, Clone)]
+pub struct Allocation {
+    pub name: String,
+    pub length: usize,
+    #[serde(default)]
+    pub global: bool,
+}
+
+impl RelocationKind {
+    // a whole word, as written by `word`, `var`, `fill` and `static`
+    pub const WORD: Self = Self {
+        words: 1,
+        offset: 0,
+        length: 16,
+    };
+
+    pub fn new(argument: &Argument, words: usize) -> Self {
+        Self {
+            words,
+            offset: argument.offset,
+            length: argument.length,
+        }
+    }
+
+    // words are stored high word first, like instructions, see `Instruction::reduce`
+    pub fn apply(&self, data: &mut [u16], value: usize) {
+        let word = data.iter().fold(0u32, |acc, word| acc << 16 | *word as u32);
+        let mask = 1u32
+            .checked_shl(self.length as u32)
+            .unwrap_or(0)
+            .wrapping_sub(1)
+            << self.offset;
+        let word = (word & !mask) | ((value as u32) << self.offset & mask);
+
+        for (index, slot) in data.iter_mut().rev().enumerate() {
+            *slot = (word >> (16 * index)) as u16;
+        }
+    }
+}
+
+impl Object {
+    // labels visible to other objects, see `global`
+    pub fn globals(&self) -> impl Iterator<Item = &str> {
+        let symbols = self
+            .symbols
+            .iter()
+            .filter(|symbol| symbol.global)
+            .map(|symbol| symbol.name.as_str());
+        let allocations = self
+            .allocations
+            .iter()
+            .filter(|allocation| allocation.global)
+            .map(|allocation| allocation.name.as_str());
+
+        symbols.chain(allocations)
+    }
+
+    // labels referenced by the object and declared by other objects, see `extern`
+    pub fn externs(&self) -> impl Iterator<Item = &str> {
+        let declared: HashSet<_> = self
+            .symbols
+            .iter()
+            .map(|symbol| symbol.name.as_str())
+            .chain(
+                self.allocations
+                    .iter()
+                    .map(|allocation| allocation.name.as_str()),
+            )
+            .collect();
+
+        self.relocations
+            .iter()
+            .filter_map(move |relocation| match &relocation.target {
+                Target::Symbol(name) if !declared.contains(name.as_str()) => Some(name.as_str()),
+                _ => None,
+            })
+    }
+
+    // lays the reduced statements out into sections, like `Statement::copy` does for images
+    pub(crate) fn build(ctx: &Context, statements: &[Statement]) -> Self {
+        let mut object = Self::default();
+        // index of the current section, none while it is absolute
+        let mut current = None;
+        let mut address = 0;
+
+        for statement in statements {
+            let chunk = match statement {
+                Statement::Section(name) => {
+                    current = ctx.is_relocatable(name).then(|| {
+                        match object
+                            .sections
+                            .iter()
+                            .position(|section| section.name == *name)
+                        {
+                            Some(index) => index,
+                            None => {
+                                object.sections.push(Section {
+                                    name: (*name).to_owned(),
+                                    region: ctx.section_regions.get(*name).cloned(),
+                                    length: 0,
+                                    alignment: 1,
+                                    chunks: Vec::new(),
+                                });
+                                object.sections.len() - 1
+                            }
+                        }
+                    });
+                    address = 0;
+                    None
+                }
+                Statement::Origin(origin) => {
+                    address = *origin;
+                    None
+                }
+                Statement::Align(alignment) => {
+                    address = address.next_multiple_of(*alignment);
+
+                    if let Some(index) = current {
+                        let section = &mut object.sections[index];
+
+                        section.alignment = section.alignment.max(*alignment);
+                    }
+
+                    None
+                }
+                Statement::Data(data, offset, _) => {
+                    Some((ChunkKind::Data, data.to_vec(), data.len(), *offset))
+                }
+                Statement::Variable(data, offset, _) => {
+                    Some((ChunkKind::Variable, data.to_vec(), data.len(), *offset))
+                }
+                Statement::Reserve(length, offset, _) => {
+                    Some((ChunkKind::Reserve, Vec::new(), *length, *offset))
+                }
+                _ => None,
+            };
+
+            let Some((kind, data, length, offset)) = chunk else {
+                if let Some(index) = current {
+                    let section = &mut object.sections[index];
+
+                    section.length = section.length.max(address);
+                }
+
+                continue;
+            };
+
+            let chunk = Chunk {
+                offset: offset.unwrap_or(address),
+                kind,
+                data,
+                length,
+            };
+
+            if offset.is_some() {
+                object.absolute.push(chunk);
+                continue;
+            }
+
+            address += length;
+
+            match current {
+                Some(index) => {
+                    let section = &mut object.sections[index];
+
+                    section.length = section.length.max(address);
+                    section.chunks.push(chunk);
+                }
+                None => object.absolute.push(chunk),
+            }
+        }
+
+        object.symbols = ctx
+            .label_files
+            .iter()
+            .filter_map(|(name, file)| {
+                let value = (*ctx.labels.get(name)?)?;
+
+                Some(Symbol {
+                    name: name.clone(),
+                    section: ctx.label_sections.get(name).cloned(),
+                    value,
+                    global: ctx.globals.contains(name),
+                    file: Some(*file),
+                })
+            })
+            .collect();
+        object.symbols.sort_by(|a, b| a.name.cmp(&b.name));
+        object.relocations = ctx.relocations.clone();
+        object.allocations = ctx
+            .allocations
+            .iter()
+            .map(|(name, length)| Allocation {
+                name: name.clone(),
+                length: *length,
+                global: ctx.globals.contains(name),
+            })
+            .collect();
+        object.allocations.sort_by(|a, b| a.name.cmp(&b.name));
+
+        object
+    }
+}
+
+impl Display for Object {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&toml::to_string(self).map_err(|_| std::fmt::Error)?)
+    }
+}
+
+impl FromStr for Object {
+    type Err = toml::de::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        toml::from_str(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, str::FromStr};
+
+    use super::{ChunkKind, Object, Target};
+    use crate::tests::object;
+
+    #[test]
+    fn round_trip() {
+        let object = object(
+            "global start, buf\nextern print\nstart: jmp print\nword end - start, #1\nend:\nsection data\nmsg: word msg\nalloc buf, #2\nstatic #0x40, #7",
+        );
+        let text = object.to_string();
+        let parsed = Object::from_str(&text).unwrap();
+
+        assert_eq!(parsed.to_string(), text);
+        assert_eq!(parsed.files, [PathBuf::from("main.asm")]);
+        assert_eq!(parsed.sections.len(), 2);
+        assert_eq!(parsed.sections[0].chunks[0].kind, ChunkKind::Data);
+        assert_eq!(parsed.absolute[0].offset, 0x40);
+        assert_eq!(parsed.globals().collect::<Vec<_>>(), ["start", "buf"]);
+        assert_eq!(parsed.externs().collect::<Vec<_>>(), ["print"]);
+        assert!(parsed.relocations.iter().any(|relocation| {
+            relocation.target == Target::Section("data".to_owned()) && relocation.file == Some(0)
+        }));
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Object::from_str("sections = 1").is_err());
+        assert!(Object::from_str("").unwrap().sections.is_empty());
+    }
+}
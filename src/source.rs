@@ -0,0 +1,957 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt::Display,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    asm::{parse_definition, parse_line, raw_arguments, syntax_error},
+    ast::{Expr, Macro, ReduceError, Statement},
+    fs::FileProvider,
+};
+
+#[derive(Debug)]
+pub struct Source {
+    pub path: PathBuf,
+    pub content: String,
+    // line of `path` the content starts at, macro expansions start at the macro body
+    pub first_line: usize,
+    // sources included by this one or expanded from its macro invocations and
+    // repetitions, by line
+    pub includes: HashMap<usize, Vec<usize>>,
+    // binary files included by this one, by line
+    pub binaries: HashMap<usize, Box<[u8]>>,
+    // lines of macro definitions and repetition bodies, which are not assembled in place
+    pub definitions: Vec<Range<usize>>,
+    pub expansion: Option<Expansion>,
+    // labels declared in the body of the expansion, renamed to be unique to it
+    pub generated: HashSet<String>,
+}
+
+// where a macro or repetition was expanded from
+#[derive(Debug)]
+pub struct Expansion {
+    pub name: String,
+    pub file: usize,
+    pub line: usize,
+    pub depth: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub parameters: Vec<String>,
+    pub body: Vec<String>,
+    pub file: usize,
+    // line of the defining file the body starts at
+    pub line: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct Sources {
+    pub files: Vec<Source>,
+    pub macros: HashMap<String, Definition>,
+}
+
+#[derive(Debug)]
+pub enum SourceError {
+    NotFound {
+        path: String,
+        file: PathBuf,
+        line: usize,
+    },
+    Cycle(Vec<PathBuf>),
+    // line that does not parse, with the column it stops at
+    InvalidSyntax {
+        file: PathBuf,
+        line: usize,
+        column: usize,
+    },
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    InvalidRepetition {
+        file: PathBuf,
+        line: usize,
+    },
+    UnterminatedBlock {
+        name: String,
+        file: PathBuf,
+        line: usize,
+    },
+    MacroArguments {
+        name: String,
+        expected: usize,
+        found: usize,
+        file: PathBuf,
+        line: usize,
+    },
+    ExpansionDepth {
+        name: String,
+        file: PathBuf,
+        line: usize,
+    },
+}
+
+// nested macro invocations deeper than this are assumed to be endless recursion
+const MAX_EXPANSION_DEPTH: usize = 64;
+
+// macro definition or repetition being read
+struct Block {
+    // macro name, or the repetition directive
+    name: String,
+    definition: Definition,
+    // arguments of each repetition, none for macro definitions
+    repetitions: Option<Vec<Vec<String>>>,
+    start: usize,
+    level: usize,
+}
+
+struct Loader<'f> {
+    files: &'f dyn FileProvider,
+    include_paths: &'f [PathBuf],
+    // constants given with `-D`, conditions made only of them are decided while loading
+    defines: &'f HashMap<String, usize>,
+    // canonical paths of the sources being loaded, for finding include cycles
+    stack: Vec<PathBuf>,
+    conditions: Vec<Branch>,
+}
+
+// conditional block being loaded, unknown when its condition depends on labels or symbols,
+// which are left to the assembler
+struct Branch {
+    active: Option<bool>,
+    taken: Option<bool>,
+}
+
+impl Source {
+    fn new(path: PathBuf, content: String) -> Self {
+        Self {
+            path,
+            content,
+            first_line: 1,
+            includes: HashMap::new(),
+            binaries: HashMap::new(),
+            definitions: Vec::new(),
+            expansion: None,
+            generated: HashSet::new(),
+        }
+    }
+
+    // lines to be assembled, with their index
+    fn lines(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.content.lines().enumerate().filter(|(index, _)| {
+            !self
+                .definitions
+                .iter()
+                .any(|definition| definition.contains(index))
+        })
+    }
+}
+
+impl Sources {
+    pub fn load(
+        files: &dyn FileProvider,
+        entry: impl AsRef<Path>,
+        include_paths: &[PathBuf],
+        defines: &HashMap<String, usize>,
+    ) -> Result<Self, SourceError> {
+        let path = entry.as_ref().to_path_buf();
+        let content = read(files, &path)?;
+
+        Self::from_buf(files, path, content, include_paths, defines)
+    }
+
+    // the path is used for resolving includes and in diagnostics
+    pub fn from_buf(
+        files: &dyn FileProvider,
+        path: impl Into<PathBuf>,
+        content: impl Into<String>,
+        include_paths: &[PathBuf],
+        defines: &HashMap<String, usize>,
+    ) -> Result<Self, SourceError> {
+        let mut sources = Self::default();
+        let mut loader = Loader {
+            files,
+            include_paths,
+            defines,
+            stack: Vec::new(),
+            conditions: Vec::new(),
+        };
+
+        sources.add(&mut loader, Source::new(path.into(), content.into()))?;
+
+        Ok(sources)
+    }
+
+    // loads the includes and expands the macro invocations of a source, macros are
+    // defined from their definition onwards, and blocks known not to be assembled are skipped
+    fn add(&mut self, loader: &mut Loader, source: Source) -> Result<usize, SourceError> {
+        let index = self.files.len();
+        let content = source.content.clone();
+        let first_line = source.first_line;
+
+        loader.stack.push(loader.files.canonicalize(&source.path));
+        self.files.push(source);
+
+        let mut block: Option<Block> = None;
+
+        for (number, text) in content.lines().enumerate() {
+            let line = first_line + number;
+
+            if let Some(current) = &mut block {
+                let (opens, closes) = match current.repetitions {
+                    Some(_) => (
+                        matches!(mnemonic(text), Some("rept" | "irp")),
+                        mnemonic(text) == Some("endr"),
+                    ),
+                    None => (
+                        parse_definition(text).is_some(),
+                        mnemonic(text) == Some("endm"),
+                    ),
+                };
+
+                if closes && current.level == 0 {
+                    let Block {
+                        name,
+                        definition,
+                        repetitions,
+                        start,
+                        ..
+                    } = block.take().unwrap();
+
+                    match repetitions {
+                        None => {
+                            self.files[index].definitions.push(start..number + 1);
+                            self.macros.insert(name, definition);
+                        }
+                        // the header stays in place of the repeated body
+                        Some(repetitions) => {
+                            let expanded = self.expand(
+                                loader,
+                                &name,
+                                &definition,
+                                &repetitions,
+                                index,
+                                first_line + start,
+                            )?;
+
+                            self.files[index].definitions.push(start + 1..number + 1);
+                            self.files[index].includes.insert(start, expanded);
+                        }
+                    }
+                } else {
+                    if opens {
+                        current.level += 1;
+                    } else if closes {
+                        current.level -= 1;
+                    }
+
+                    current.definition.body.push(text.to_owned());
+                }
+
+                continue;
+            }
+
+            if let Some((name, parameters)) = parse_definition(text) {
+                block = Some(Block {
+                    name: name.to_owned(),
+                    definition: Definition {
+                        parameters: parameters.into_iter().map(str::to_owned).collect(),
+                        body: Vec::new(),
+                        file: index,
+                        line: line + 1,
+                    },
+                    repetitions: None,
+                    start: number,
+                    level: 0,
+                });
+
+                continue;
+            }
+
+            if let Some(directive @ ("rept" | "irp")) = mnemonic(text) {
+                let (parameters, repetitions) = match loader.is_active() {
+                    Some(false) => (Vec::new(), Vec::new()),
+                    _ => repetitions(directive, text, loader.defines).ok_or_else(|| {
+                        SourceError::InvalidRepetition {
+                            file: self.files[index].path.clone(),
+                            line,
+                        }
+                    })?,
+                };
+
+                block = Some(Block {
+                    name: directive.to_owned(),
+                    definition: Definition {
+                        parameters,
+                        body: Vec::new(),
+                        file: index,
+                        line: line + 1,
+                    },
+                    repetitions: Some(repetitions),
+                    start: number,
+                    level: 0,
+                });
+
+                continue;
+            }
+
+            let Some(statements) = parse_line(text, index) else {
+                return Err(SourceError::InvalidSyntax {
+                    file: self.files[index].path.clone(),
+                    line,
+                    column: syntax_error(text).unwrap_or(1),
+                });
+            };
+
+            for statement in statements {
+                let active = loader.is_active();
+
+                match statement {
+                    Statement::Macro(r#macro) if r#macro.conditional().is_some() => {
+                        loader.branch(&r#macro)
+                    }
+                    _ if active == Some(false) => {}
+                    Statement::Macro(r#macro) => {
+                        // files missing from blocks that cannot be decided yet are reported
+                        // if the block is assembled, see `ReduceError::MissingFile`
+                        if let Some(request) = r#macro.include() {
+                            match self.include(loader, index, request, line) {
+                                Ok(included) => {
+                                    self.files[index].includes.insert(number, vec![included]);
+                                }
+                                Err(SourceError::NotFound { .. }) if active.is_none() => {}
+                                Err(err) => return Err(err),
+                            }
+                        } else if let Some(request) = r#macro.incbin() {
+                            let path = match self.resolve(loader, index, request, line) {
+                                Ok(path) => path,
+                                Err(SourceError::NotFound { .. }) if active.is_none() => continue,
+                                Err(err) => return Err(err),
+                            };
+                            let content =
+                                loader.files.read(&path).map_err(|error| SourceError::Io {
+                                    path: path.clone(),
+                                    error,
+                                })?;
+
+                            self.files[index]
+                                .binaries
+                                .insert(number, content.into_boxed_slice());
+                        }
+                    }
+                    Statement::Instruction(instruction)
+                        if self.macros.contains_key(instruction.pair.as_str()) =>
+                    {
+                        let name = instruction.pair.as_str();
+                        let definition = self.macros[name].clone();
+                        let arguments = raw_arguments(text).unwrap_or_default();
+
+                        if arguments.len() != definition.parameters.len() {
+                            return Err(SourceError::MacroArguments {
+                                name: name.to_owned(),
+                                expected: definition.parameters.len(),
+                                found: arguments.len(),
+                                file: self.files[index].path.clone(),
+                                line,
+                            });
+                        }
+
+                        let arguments = [arguments.into_iter().map(str::to_owned).collect()];
+                        let expanded =
+                            self.expand(loader, name, &definition, &arguments, index, line)?;
+
+                        self.files[index].includes.insert(number, expanded);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        if let Some(Block { name, start, .. }) = block {
+            return Err(SourceError::UnterminatedBlock {
+                name,
+                file: self.files[index].path.clone(),
+                line: first_line + start,
+            });
+        }
+
+        loader.stack.pop();
+
+        Ok(index)
+    }
+
+    fn resolve(
+        &self,
+        loader: &Loader,
+        index: usize,
+        request: String,
+        line: usize,
+    ) -> Result<PathBuf, SourceError> {
+        let file = &self.files[index].path;
+
+        resolve(loader.files, file, &request, loader.include_paths).ok_or_else(|| {
+            SourceError::NotFound {
+                path: request,
+                file: file.clone(),
+                line,
+            }
+        })
+    }
+
+    fn include(
+        &mut self,
+        loader: &mut Loader,
+        index: usize,
+        request: String,
+        line: usize,
+    ) -> Result<usize, SourceError> {
+        let path = self.resolve(loader, index, request, line)?;
+
+        if loader.stack.contains(&loader.files.canonicalize(&path)) {
+            let mut cycle = loader.stack.clone();
+            cycle.push(path);
+
+            return Err(SourceError::Cycle(cycle));
+        }
+
+        let content = read(loader.files, &path)?;
+
+        self.add(loader, Source::new(path, content))
+    }
+
+    // expands the body once for each set of arguments, parameters are replaced by the
+    // arguments as written, and labels declared in the body are made unique to each expansion
+    fn expand(
+        &mut self,
+        loader: &mut Loader,
+        name: &str,
+        definition: &Definition,
+        instances: &[Vec<String>],
+        index: usize,
+        line: usize,
+    ) -> Result<Vec<usize>, SourceError> {
+        let depth = self.files[index]
+            .expansion
+            .as_ref()
+            .map_or(0, |expansion| expansion.depth)
+            + 1;
+
+        if depth > MAX_EXPANSION_DEPTH {
+            return Err(SourceError::ExpansionDepth {
+                name: name.to_owned(),
+                file: self.files[index].path.clone(),
+                line,
+            });
+        }
+
+        let labels = definition
+            .body
+            .iter()
+            .filter_map(|line| parse_line(line, 0))
+            .flatten()
+            .filter_map(|statement| match statement {
+                // local labels are renamed along with their dots, see `substitute`
+                Statement::Label(label) if is_word(label.pair.as_str().trim_start_matches('.')) => {
+                    Some(label.pair.as_str())
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        instances
+            .iter()
+            .map(|arguments| {
+                let suffix = format!("__{}", self.files.len());
+                let replacements = labels
+                    .iter()
+                    .map(|label| (*label, format!("{label}{suffix}")))
+                    .chain(
+                        definition
+                            .parameters
+                            .iter()
+                            .map(String::as_str)
+                            .zip(arguments.iter().cloned()),
+                    )
+                    .collect::<HashMap<_, _>>();
+
+                let content = definition
+                    .body
+                    .iter()
+                    .map(|line| substitute(line, &replacements))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+
+                let source = Source {
+                    first_line: definition.line,
+                    expansion: Some(Expansion {
+                        name: name.to_owned(),
+                        file: index,
+                        line,
+                        depth,
+                    }),
+                    generated: labels
+                        .iter()
+                        .map(|label| format!("{label}{suffix}"))
+                        .collect(),
+                    ..Source::new(self.files[definition.file].path.clone(), content)
+                };
+
+                self.add(loader, source)
+            })
+            .collect()
+    }
+
+    // parses every source, replacing includes and macro invocations by the statements of
+    // the included or expanded source, and the paths of binary includes by their contents
+    pub fn statements(&self) -> Vec<Statement<'_>> {
+        let mut result = Vec::new();
+
+        if !self.files.is_empty() {
+            self.collect(0, &mut result);
+        }
+
+        result
+    }
+
+    fn collect<'s>(&'s self, index: usize, result: &mut Vec<Statement<'s>>) {
+        let source = &self.files[index];
+
+        for (number, line) in source.lines() {
+            let included = source.includes.get(&number);
+
+            for statement in parse_line(line, index).into_iter().flatten() {
+                match statement {
+                    Statement::Label(mut label) => {
+                        label.scoped = !source.generated.contains(label.pair.as_str());
+                        result.push(Statement::Label(label))
+                    }
+                    _ if included.is_some() => {
+                        for included in included.unwrap() {
+                            self.collect(*included, result)
+                        }
+                    }
+                    Statement::Macro(mut r#macro) if r#macro.incbin().is_some() => {
+                        if let Some(value) = source.binaries.get(&number) {
+                            let path = &mut r#macro.arguments.expr_list[0];
+
+                            *path = Expr::Binary {
+                                pair: path.pair(),
+                                value,
+                            };
+                        }
+
+                        result.push(Statement::Macro(r#macro))
+                    }
+                    statement => result.push(statement),
+                }
+            }
+        }
+    }
+
+    // finds the source, line and column of a slice of one of the sources
+    fn find(&self, text: &str) -> Option<(usize, usize, usize)> {
+        let address = text.as_ptr() as usize;
+
+        self.files.iter().enumerate().find_map(|(index, source)| {
+            let offset = address
+                .checked_sub(source.content.as_ptr() as usize)
+                .filter(|offset| *offset <= source.content.len())?;
+            let before = &source.content[..offset];
+            let line = before.matches('\n').count() + source.first_line;
+            let column = offset - before.rfind('\n').map_or(0, |index| index + 1) + 1;
+
+            Some((index, line, column))
+        })
+    }
+
+    // finds the file, line and column of a slice of one of the sources
+    pub fn locate(&self, text: &str) -> Option<(&Path, usize, usize)> {
+        self.find(text)
+            .map(|(index, line, column)| (self.files[index].path.as_path(), line, column))
+    }
+
+    // errors inside macro expansions are followed by the invocations they come from
+    pub fn describe(&self, err: &ReduceError) -> String {
+        let Some((index, line, column)) = err.pair().and_then(|pair| self.find(pair.as_str()))
+        else {
+            return err.to_string();
+        };
+
+        // the statement an overlap runs into is pointed at on a line of its own
+        let text = match err {
+            ReduceError::Overlap { address, .. } => format!("Overlap {{ address: {address:#x} }}"),
+            err => err.to_string(),
+        };
+
+        let mut source = &self.files[index];
+        let mut message = format!("{}:{line}:{column}: {text}", source.path.display());
+
+        while let Some(expansion) = &source.expansion {
+            source = &self.files[expansion.file];
+            message.push_str(&format!(
+                "\nin expansion of `{}` at {}:{}",
+                expansion.name,
+                source.path.display(),
+                expansion.line
+            ));
+        }
+
+        if let ReduceError::Overlap { previous, .. } = err
+            && let Some((path, line, column)) = self.locate(previous.as_str())
+        {
+            message.push_str(&format!("\noverlaps {}:{line}:{column}", path.display()));
+        }
+
+        message
+    }
+}
+
+impl Loader<'_> {
+    // whether the line being loaded is assembled, unknown when it depends on a condition the
+    // assembler decides
+    fn is_active(&self) -> Option<bool> {
+        if self
+            .conditions
+            .iter()
+            .any(|branch| branch.active == Some(false))
+        {
+            Some(false)
+        } else {
+            self.conditions
+                .iter()
+                .all(|branch| branch.active.is_some())
+                .then_some(true)
+        }
+    }
+
+    // follows conditional directives the way `Macro::reduce` evaluates them, unbalanced
+    // blocks are reported when assembling
+    fn branch(&mut self, r#macro: &Macro) {
+        let condition = self.condition(r#macro);
+
+        match (r#macro.directive, self.conditions.last_mut()) {
+            ("if" | "ifdef" | "ifndef", _) => self.conditions.push(Branch {
+                active: condition,
+                taken: condition,
+            }),
+            ("elif", Some(branch)) => {
+                let active = match branch.taken {
+                    Some(true) => Some(false),
+                    taken => condition.filter(|active| taken.is_some() || !active),
+                };
+
+                branch.taken = match (branch.taken, active) {
+                    (Some(true), _) | (_, Some(true)) => Some(true),
+                    (Some(false), Some(false)) => Some(false),
+                    _ => None,
+                };
+                branch.active = active;
+            }
+            ("else", Some(branch)) => {
+                branch.active = branch.taken.map(|taken| !taken);
+                branch.taken = Some(true);
+            }
+            ("endif", Some(_)) => {
+                self.conditions.pop();
+            }
+            _ => {}
+        }
+    }
+
+    // conditions on constants only, anything else may be a label or a symbol
+    fn condition(&self, r#macro: &Macro) -> Option<bool> {
+        let expr = r#macro.arguments.expr_list.first()?;
+
+        match r#macro.directive {
+            directive @ ("ifdef" | "ifndef") => match expr {
+                Expr::LabelRef { name, .. } if self.defines.contains_key(*name) => {
+                    Some(directive == "ifdef")
+                }
+                _ => None,
+            },
+            _ => expr.constant(self.defines).map(|value| value != 0),
+        }
+    }
+}
+
+pub fn read(files: &dyn FileProvider, path: &Path) -> Result<String, SourceError> {
+    files.read_to_string(path).map_err(|error| SourceError::Io {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+// `rept count, counter?` repeats the body count times, with the counter going up from #0,
+// `irp name, values*` repeats it once for each value, returns the parameters of the body
+// and the arguments of each repetition
+fn repetitions(
+    directive: &str,
+    line: &str,
+    defines: &HashMap<String, usize>,
+) -> Option<(Vec<String>, Vec<Vec<String>>)> {
+    let arguments = raw_arguments(line)?;
+
+    match (directive, arguments.as_slice()) {
+        ("rept", [_, counter @ ..]) if counter.len() <= 1 && counter.iter().all(|c| is_word(c)) => {
+            let count = parse_line(line, 0)?.find_map(|statement| match statement {
+                Statement::Instruction(instruction) => {
+                    instruction.arguments.expr_list[0].constant(defines)
+                }
+                _ => None,
+            })?;
+
+            Some((
+                counter.iter().map(|counter| counter.to_string()).collect(),
+                (0..count)
+                    .map(|index| counter.iter().map(|_| format!("#{index}")).collect())
+                    .collect(),
+            ))
+        }
+        ("irp", [name, values @ ..]) if is_word(name) => Some((
+            vec![name.to_string()],
+            values.iter().map(|value| vec![value.to_string()]).collect(),
+        )),
+        _ => None,
+    }
+}
+
+// mnemonic of the instruction in a line, if any
+fn mnemonic(line: &str) -> Option<&str> {
+    parse_line(line, 0)?.find_map(|statement| match statement {
+        Statement::Instruction(instruction) => Some(instruction.pair.as_str()),
+        _ => None,
+    })
+}
+
+fn is_word(text: &str) -> bool {
+    text.starts_with(|chr: char| chr.is_ascii_alphabetic() || chr == '_')
+        && text
+            .chars()
+            .all(|chr| chr.is_ascii_alphanumeric() || chr == '_')
+}
+
+// replaces whole words found in the replacements, leaving strings, comments and subwords
+// untouched
+fn substitute(line: &str, replacements: &HashMap<&str, String>) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut chars = line.char_indices().peekable();
+    let mut quote = None;
+    let mut previous = None;
+
+    while let Some((start, chr)) = chars.next() {
+        match (quote, chr) {
+            (Some(_), '\\') => {
+                result.push(chr);
+                result.extend(chars.next().map(|(_, chr)| chr));
+            }
+            (Some(delimiter), _) => {
+                if chr == delimiter {
+                    quote = None;
+                }
+
+                result.push(chr);
+            }
+            (None, ';') => {
+                result.push_str(&line[start..]);
+                break;
+            }
+            (None, '"' | '\'') => {
+                quote = Some(chr);
+                result.push(chr);
+            }
+            (None, _) if chr.is_ascii_alphanumeric() || chr == '_' => {
+                let mut end = start + 1;
+
+                while let Some((index, _)) =
+                    chars.next_if(|(_, chr)| chr.is_ascii_alphanumeric() || *chr == '_')
+                {
+                    end = index + 1;
+                }
+
+                let word = &line[start..end];
+                // local labels are found with their dots, unless they follow another label,
+                // e.g. `.x` but not `scope.x`
+                let before = line[..start].trim_end_matches('.');
+                let dotted = &line[before.len()..end];
+                let is_local = dotted != word
+                    && !before.ends_with(|chr: char| chr.is_ascii_alphanumeric() || chr == '_');
+
+                let replacement = match replacements.get(dotted) {
+                    Some(replacement) if is_local => {
+                        Some(&replacement[dotted.len() - word.len()..])
+                    }
+                    _ => replacements
+                        .get(word)
+                        .filter(|_| previous != Some('.') && is_word(word))
+                        .map(String::as_str),
+                };
+
+                match replacement {
+                    // a `#` written before a parameter is not doubled by an argument with one
+                    Some(replacement) if result.ends_with('#') => {
+                        result.push_str(replacement.strip_prefix('#').unwrap_or(replacement))
+                    }
+                    Some(replacement) => result.push_str(replacement),
+                    None => result.push_str(word),
+                }
+            }
+            _ => result.push(chr),
+        }
+
+        previous = result.chars().next_back();
+    }
+
+    result
+}
+
+// includes are looked up next to the including file first, then in the include paths
+fn resolve(
+    files: &dyn FileProvider,
+    file: &Path,
+    request: &str,
+    include_paths: &[PathBuf],
+) -> Option<PathBuf> {
+    let base = file.parent().unwrap_or(Path::new(""));
+
+    std::iter::once(base.join(request))
+        .chain(include_paths.iter().map(|path| path.join(request)))
+        .find(|path| files.is_file(path))
+}
+
+impl Display for SourceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("{self:#?}"))
+    }
+}
+
+impl Error for SourceError {}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{SourceError, Sources};
+    use crate::{
+        Options,
+        ast::ReduceError,
+        tests::{load, parse_files, provider},
+    };
+
+    #[test]
+    fn includes() {
+        let files = provider(&[
+            ("main.asm", "include \"lib/a.asm\"\nword #1"),
+            ("lib/a.asm", "include \"b.asm\"\nword #2"),
+            ("inc/b.asm", "word #3"),
+        ]);
+        let options = Options {
+            include_paths: vec!["inc".into()],
+            ..Default::default()
+        };
+
+        assert_eq!(parse_files(&files, &options).unwrap()[..3], [3, 2, 1]);
+    }
+
+    #[test]
+    fn include_cycles() {
+        let files = provider(&[
+            ("main.asm", "include \"a.asm\""),
+            ("a.asm", "include \"./main.asm\""),
+        ]);
+        let result = Sources::load(&files, "main.asm", &[], &HashMap::new());
+
+        assert!(matches!(result, Err(SourceError::Cycle(cycle)) if cycle.len() == 3));
+    }
+
+    #[test]
+    fn untaken_includes() {
+        let options = Options {
+            defines: HashMap::from([("BOARD".to_owned(), 2)]),
+            ..Default::default()
+        };
+        let files = provider(&[(
+            "main.asm",
+            "ifdef NOPE\ninclude \"nope.asm\"\nincbin \"nope.bin\"\nendif\nword #1",
+        )]);
+
+        assert_eq!(parse_files(&files, &options).unwrap()[0], 1);
+
+        let files = provider(&[(
+            "main.asm",
+            "if BOARD - #2\ninclude \"nope.asm\"\nelse\nword #3\nendif",
+        )]);
+
+        assert_eq!(parse_files(&files, &options).unwrap()[0], 3);
+
+        // decided by a label, the missing file is only an error when the block is assembled
+        let files = provider(&[(
+            "main.asm",
+            "x: halt\nifndef x\ninclude \"nope.asm\"\nendif\nifdef x\nincbin \"nope.bin\"\nendif",
+        )]);
+
+        assert!(matches!(
+            parse_files(&files, &options),
+            Err(ReduceError::MissingFile(pair)) if pair.as_str() == "\"nope.bin\""
+        ));
+    }
+
+    #[test]
+    fn repetitions() {
+        let options = Options {
+            defines: HashMap::from([("N".to_owned(), 3)]),
+            ..Default::default()
+        };
+        let files = provider(&[(
+            "main.asm",
+            "rept N, i\nword i\nendr\nirp v, #5, #6\nword v\nendr\nrept #1\nl: jmp l\nendr",
+        )]);
+
+        assert_eq!(
+            parse_files(&files, &options).unwrap()[..7],
+            [0, 1, 2, 5, 6, 0x800, 5]
+        );
+
+        let files = provider(&[("main.asm", "rept M\nword #1\nendr")]);
+        let result = Sources::load(&files, "main.asm", &[], &options.defines);
+
+        assert!(matches!(
+            result,
+            Err(SourceError::InvalidRepetition { line: 1, .. })
+        ));
+
+        let files = provider(&[("main.asm", "rept #2\nword #1")]);
+        let result = Sources::load(&files, "main.asm", &[], &options.defines);
+
+        assert!(matches!(result, Err(SourceError::UnterminatedBlock { .. })));
+    }
+
+    #[test]
+    fn missing_includes() {
+        let files = provider(&[("main.asm", "word #1\ninclude \"nope.asm\"")]);
+        let result = Sources::load(&files, "main.asm", &[], &HashMap::new());
+
+        assert!(matches!(result, Err(SourceError::NotFound { line: 2, .. })));
+    }
+
+    #[test]
+    fn invalid_syntax() {
+        assert!(matches!(
+            load("word #1\nword #2,\nword #3"),
+            Err(SourceError::InvalidSyntax {
+                line: 2,
+                column: 9,
+                ..
+            })
+        ));
+
+        // lines of a macro are checked once expanded, at their line in the body
+        assert!(matches!(
+            load("macro m\nword #1\nword %\nendm\nhalt\nm"),
+            Err(SourceError::InvalidSyntax { line: 3, .. })
+        ));
+    }
+}
@@ -0,0 +1,692 @@
+use std::{collections::HashMap, path::Path, str::FromStr};
+
+use crate::{
+    Options, assemble_from, assemble_from_buf, assemble_from_buf_with,
+    ast::ReduceError,
+    cis::{InstructionSet, RegionAttribute},
+    fs::MemoryFileProvider,
+    object::Object,
+    source::{self, SourceError, Sources},
+};
+
+pub const SYNTAX: &str = r#"
+[symbols]
+r0 = { value = 0, tags = ["reg"] }
+r1 = { value = 1, tags = ["reg"] }
+
+[[instructions.loadn]]
+value = 0b11100000_00000000_00000000_00000000
+length = 32
+arguments = [
+    { type = "reg", index = 0, offset = 23, length = 3 },
+    { type = "u16", index = 1, offset = 0, length = 16 },
+]
+
+[[instructions.jmp]]
+value = 0b00001000_00000000_00000000_00000000
+length = 32
+arguments = [
+    { type = "ptr16", index = 0, offset = 0, length = 16 },
+]
+
+[[instructions.halt]]
+value = 0b0011110000000000
+length = 16
+arguments = []
+
+[charmaps.shout]
+a = 65
+b = 66
+"#;
+
+// project files with the test syntax, builds start from `main.asm`
+pub fn provider(sources: &[(&str, &str)]) -> MemoryFileProvider {
+    let mut files = MemoryFileProvider::new();
+
+    files.insert("syntax.toml", SYNTAX);
+
+    for (path, content) in sources {
+        files.insert(path, *content);
+    }
+
+    files
+}
+
+// reduces `main.asm` the way a build with the options does, the sources are leaked so that
+// errors can be matched on
+pub fn parse_files(
+    files: &MemoryFileProvider,
+    options: &Options,
+) -> Result<Box<[u16]>, ReduceError<'static>> {
+    let syntax = source::read(files, Path::new("syntax.toml")).unwrap();
+    let is = InstructionSet::from_str(&syntax).unwrap();
+    let sources = Sources::load(files, "main.asm", &options.include_paths, &options.defines);
+    let sources = Box::leak(Box::new(sources.unwrap()));
+    let mut ctx = crate::context(&is, options);
+
+    crate::parse(&mut ctx, sources)
+}
+
+pub fn parse(source: &str) -> Result<Box<[u16]>, ReduceError<'static>> {
+    parse_files(&provider(&[("main.asm", source)]), &Options::default())
+}
+
+// loads the sources of a build, for the errors found before assembling
+pub fn load(source: &str) -> Result<Sources, SourceError> {
+    Sources::load(
+        &provider(&[("main.asm", source)]),
+        "main.asm",
+        &[],
+        &HashMap::new(),
+    )
+}
+
+// relocatable object of a source
+pub fn object(source: &str) -> Object {
+    let options = Options {
+        relocatable: true,
+        ..Default::default()
+    };
+
+    match assemble_from(
+        &provider(&[("main.asm", source)]),
+        "main.asm",
+        "syntax.toml",
+        &options,
+    ) {
+        Ok(assembly) => assembly.object().unwrap().clone(),
+        Err(err) => panic!("{err}"),
+    }
+}
+
+// first words of the image
+pub fn words(source: &str, count: usize) -> Vec<u16> {
+    match parse(source) {
+        Ok(data) => data[..count].to_vec(),
+        Err(err) => panic!("{err}"),
+    }
+}
+
+#[test]
+fn escapes() {
+    assert_eq!(
+        words(r#"ascii "a\n\t\0\x41\u{263A}\"\\\'""#, 9),
+        [0x61, 0x0a, 0x09, 0, 0x41, 0x263a, 0x22, 0x5c, 0x27]
+    );
+    assert_eq!(words("ascii 'say \"hi\"'", 8), b"say \"hi\"".map(u16::from));
+    assert!(matches!(
+        load("ascii \"a\\qb\"\nword #5"),
+        Err(SourceError::InvalidSyntax { line: 1, .. })
+    ));
+}
+
+#[test]
+fn strings() {
+    assert_eq!(words("string \"ab\"\nword #1", 4), [97, 98, 0, 1]);
+    assert_eq!(words("pstring \"ab\"\npstring \"\"", 4), [2, 97, 98, 0]);
+    assert_eq!(
+        words("packed \"abc\"\npacked \"ab\"", 4),
+        [0x6162, 0x6300, 0x6162, 0]
+    );
+    assert_eq!(words("PString \"a\"\nASCII \"b\"", 3), [1, 97, 98]);
+    assert!(matches!(
+        parse("packed \"\u{263A}\""),
+        Err(ReduceError::UnencodableChar {
+            code_point: 0x263a,
+            ..
+        })
+    ));
+    assert!(matches!(
+        parse(&format!("pstring \"{}\"", "a".repeat(0x10000))),
+        Err(ReduceError::ValueOverflow { value: 0x10000, .. })
+    ));
+}
+
+#[test]
+fn string_attributes() {
+    assert_eq!(
+        words(
+            "string \"ab\", #0x100\nascii \"a\", #1\npstring \"a\", #1",
+            6
+        ),
+        [0x161, 0x162, 0, 98, 1, 98]
+    );
+    assert_eq!(
+        words("string \"a\", color\norg #4\ncolor: halt", 2),
+        [0x65, 0]
+    );
+    assert!(matches!(
+        parse("string \"a\", #0x10000"),
+        Err(ReduceError::ValueOverflow { value: 0x10000, .. })
+    ));
+}
+
+#[test]
+fn number_literals() {
+    assert_eq!(
+        words("word #0x1F, #0o17, #0b101, #0d9, #10", 5),
+        [31, 15, 5, 9, 10]
+    );
+    assert!(matches!(
+        load("word #1\nword #1 + #0x1FFFFFFFFFFFFFFFFF"),
+        Err(SourceError::InvalidSyntax {
+            line: 2,
+            column: 12,
+            ..
+        })
+    ));
+    assert_eq!(crate::parse_define("X=0x10"), Ok(("X".to_owned(), 16)));
+    assert!(crate::parse_define("X=0x1FFFFFFFFFFFFFFFFF").is_err());
+    assert!(crate::parse_symbols("x = 0x1FFFFFFFFFFFFFFFFF").is_err());
+}
+
+#[test]
+fn charmaps() {
+    assert_eq!(words("charmap shout\nascii \"abc\"", 3), [65, 66, 0x63]);
+    assert!(matches!(
+        parse("charmap quiet\nascii \"a\""),
+        Err(ReduceError::UnknownCharmap(_))
+    ));
+    assert!(matches!(
+        parse("ascii \"\\u{1F600}\""),
+        Err(ReduceError::UnencodableChar {
+            code_point: 0x1f600,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn data() {
+    assert_eq!(
+        words("word #1 - #2, #0xffff\nvar #3, \"ab\"", 5),
+        [0xffff, 0xffff, 97, 98, 0]
+    );
+    assert!(matches!(
+        parse("var #2, \"hello\""),
+        Err(ReduceError::UnexpectedArgument {
+            expected: 3,
+            found: 6,
+            ..
+        })
+    ));
+    assert!(matches!(
+        parse("word #0x12345"),
+        Err(ReduceError::ValueOverflow { value: 0x12345, .. })
+    ));
+    assert!(matches!(
+        parse("fill #2, #0x12345"),
+        Err(ReduceError::ValueOverflow { value: 0x12345, .. })
+    ));
+    assert!(matches!(
+        parse("word #0 - #0x8001"),
+        Err(ReduceError::ValueOverflow { .. })
+    ));
+}
+
+#[test]
+fn placement_directives() {
+    assert_eq!(
+        words("word #1\nalign #4\nword #2\nalign #4\norg #9\nword #3", 10),
+        [1, 0, 0, 0, 2, 0, 0, 0, 0, 3]
+    );
+    assert_eq!(
+        words("org #3\nhere: word here\norg #1\nword #2", 4),
+        [0, 2, 0, 3]
+    );
+    assert!(matches!(parse("align #0"), Err(ReduceError::TypeError)));
+    assert!(matches!(parse("org \"a\""), Err(ReduceError::TypeError)));
+}
+
+#[test]
+fn statics() {
+    let data = parse("static #4, \"Hi\", #0, end\nword #1\nend:").unwrap();
+
+    // the static data is not part of the code, so `end` follows the word
+    assert_eq!(data[..8], [1, 0, 0, 0, 72, 105, 0, 1]);
+    assert!(matches!(
+        parse("static #2, \"abc\"\nstatic #4, #1"),
+        Err(ReduceError::Overlap { address: 4, .. })
+    ));
+}
+
+// image of a source including `data.bin`
+fn parse_binary(source: &str) -> Result<Box<[u16]>, ReduceError<'static>> {
+    let mut files = provider(&[("main.asm", source)]);
+
+    files.insert("data.bin", [0x12, 0x34, 0x56, 0x78, 0x9a]);
+
+    parse_files(&files, &Options::default())
+}
+
+#[test]
+fn binary_includes() {
+    let data = parse_binary("incbin \"data.bin\"\nword #1").unwrap();
+
+    assert_eq!(data[..4], [0x1234, 0x5678, 0x9a00, 1]);
+
+    let data = parse_binary("incbin \"data.bin\", be\nincbin \"data.bin\", le").unwrap();
+
+    assert_eq!(data[..6], [0x1234, 0x5678, 0x9a00, 0x3412, 0x7856, 0x009a]);
+
+    let data = parse_binary("incbin \"data.bin\", byte").unwrap();
+
+    assert_eq!(data[..5], [0x12, 0x34, 0x56, 0x78, 0x9a]);
+
+    let data = parse_binary("incbin \"data.bin\", #1, #3, le\nincbin \"data.bin\", #3").unwrap();
+
+    assert_eq!(data[..4], [0x5634, 0x0078, 0x789a, 0]);
+    assert!(matches!(
+        parse_binary("incbin \"data.bin\", #4, #2"),
+        Err(ReduceError::BinaryRange {
+            offset: 4,
+            length: 2,
+            size: 5,
+            ..
+        })
+    ));
+    assert!(matches!(
+        parse_binary("incbin \"data.bin\", #6"),
+        Err(ReduceError::BinaryRange { offset: 6, .. })
+    ));
+}
+
+#[test]
+fn overlaps() {
+    assert!(matches!(
+        parse("org #2\nword #1\norg #1\nword #2, #3"),
+        Err(ReduceError::Overlap { address: 2, .. })
+    ));
+    assert!(matches!(
+        parse("static #1, #5\nword #1, #2"),
+        Err(ReduceError::Overlap { address: 1, .. })
+    ));
+    assert!(matches!(
+        parse("org #0xffff\nword #1, #2"),
+        Err(ReduceError::AddressOverflow {
+            address: 0xffff,
+            ..
+        })
+    ));
+    assert_eq!(words("org #2\nword #1\norg #0\nword #2, #3", 3), [2, 3, 1]);
+
+    let files = provider(&[
+        ("main.asm", "include \"table.asm\"\norg #1\nword #2, #3"),
+        ("table.asm", "org #2\nword #1"),
+    ]);
+    let err = assemble_from(&files, "main.asm", "syntax.toml", &Options::default()).err();
+
+    assert_eq!(
+        err.as_deref(),
+        Some("main.asm:3:1: Overlap { address: 0x2 }\noverlaps table.asm:2:1")
+    );
+}
+
+const REGIONS: &str = r#"
+[regions.rom]
+start = 0
+length = 16
+attributes = ["read-only"]
+
+[regions.ram]
+start = 32
+length = 16
+"#;
+
+fn parse_regions(source: &str) -> Result<Box<[u16]>, ReduceError<'static>> {
+    let mut files = provider(&[("main.asm", source)]);
+
+    files.insert("syntax.toml", format!("{SYNTAX}{REGIONS}"));
+
+    parse_files(&files, &Options::default())
+}
+
+#[test]
+fn regions() {
+    assert!(matches!(
+        parse_regions("org #20\nword #1"),
+        Err(ReduceError::UnmappedAddress { address: 20, .. })
+    ));
+    assert!(matches!(
+        parse_regions("org #14\nword #1, #2, #3"),
+        Err(ReduceError::RegionOverflow { region, .. }) if region == "rom"
+    ));
+    assert!(matches!(
+        parse_regions("var #1, #0"),
+        Err(ReduceError::InvalidRegion {
+            attribute: RegionAttribute::ReadOnly,
+            ..
+        })
+    ));
+
+    let data = parse_regions("word __ram_base, __ram_top\nsection data, ram\nvar #1, #7").unwrap();
+
+    assert_eq!(data[..2], [32, 47]);
+    assert_eq!(data[32], 7);
+}
+
+#[test]
+fn section_bounds() {
+    let source = "section data\nword #1, #2\nsection code\n\
+                  word __code_start, __code_end, __data_start, __data_end";
+
+    // sections are placed in order of first appearance, starting with `code`
+    assert_eq!(words(source, 6), [0, 4, 4, 6, 1, 2]);
+    assert_eq!(
+        words(
+            "section bss, #8\nreserve #2\nsection code\nword __bss_start, __bss_end",
+            2
+        ),
+        [8, 10]
+    );
+}
+
+#[test]
+fn placement() {
+    let placement = r#"
+[placement.rom]
+sections = ["code", "rodata"]
+
+[placement.ram]
+sections = ["data", "bss"]
+allocations = true
+"#;
+    let mut files = provider(&[(
+        "main.asm",
+        "section data\nd: var #1, #7\nsection rodata\nr: word #5\nsection bss\nreserve #2\n\
+         section code\nword d, r, buf\nalloc buf, #1",
+    )]);
+
+    files.insert("syntax.toml", format!("{SYNTAX}{REGIONS}{placement}"));
+
+    let data = parse_files(&files, &Options::default()).unwrap();
+
+    assert_eq!(data[..4], [32, 3, 35, 5]);
+    assert_eq!(data[32], 7);
+}
+
+#[test]
+fn sections() {
+    assert_eq!(
+        words(
+            "section data\nword #1\nsection code\nword #2\nsection data\nword #3",
+            3
+        ),
+        [2, 1, 3]
+    );
+
+    // a section continues where it was left, not at its fixed address
+    assert_eq!(
+        words(
+            "section data, #4\nword #1\nsection code\nword #2\nsection data\nword #3",
+            6
+        ),
+        [2, 0, 0, 0, 1, 3]
+    );
+    assert_eq!(
+        words(
+            "section data, #4\nword #1\nsection data\nword #2, __data_start",
+            7
+        )[4..],
+        [1, 2, 4]
+    );
+}
+
+#[test]
+fn invalid_regions() {
+    let empty = format!("{SYNTAX}[regions.none]\nstart = 0\nlength = 0\n");
+    let overlapping = format!("{SYNTAX}{REGIONS}[regions.vram]\nstart = 40\nlength = 16\n");
+
+    for syntax in [empty, overlapping] {
+        assert!(InstructionSet::from_str(&syntax).is_err());
+    }
+}
+
+#[test]
+fn conditionals() {
+    let source = "a: word #1\nif a + #1\nword #2\nelif #1\nword #3\nelse\nword #4\nendif\nword #5";
+
+    assert_eq!(words(source, 3), [1, 2, 5]);
+    assert_eq!(
+        words(
+            "ifdef a\nword #1\nelse\nword #2\nendif\nifndef r0\nword #3\nendif",
+            2
+        ),
+        [2, 0]
+    );
+    assert!(matches!(
+        parse("if later\nendif\nlater: halt"),
+        Err(ReduceError::UnresolvedCondition(_))
+    ));
+    assert!(matches!(
+        parse("ifdef later\nendif\nlater: halt"),
+        Err(ReduceError::UnresolvedCondition(_))
+    ));
+    assert!(matches!(
+        parse("if #1\nword #1"),
+        Err(ReduceError::UnbalancedConditional(_))
+    ));
+    assert!(matches!(
+        parse("if #1\nsection data\nendif"),
+        Err(ReduceError::SectionInConditional(_))
+    ));
+}
+
+#[test]
+fn macros() {
+    let source = "macro pair a, b\nword a, b\nendm\npair #1, #2\npair x, #3\nx: word #9";
+
+    assert_eq!(words(source, 5), [1, 2, 4, 3, 9]);
+    assert_eq!(
+        words("macro spin\nloop: jmp loop\nendm\nspin\nspin", 4),
+        [0x800, 0, 0x800, 2]
+    );
+    assert_eq!(
+        words(
+            "macro down n\nif n\nword n\ndown n - #1\nendif\nendm\ndown #3",
+            4
+        ),
+        [3, 2, 1, 0]
+    );
+    // labels of an expansion do not open a scope for the local labels after it
+    assert_eq!(
+        words(
+            "macro spin\nl: jmp l\nendm\nfunc:\nspin\n.end: word func.end",
+            3
+        ),
+        [0x800, 0, 2]
+    );
+    assert_eq!(
+        words("f:\nmacro spin\n.l: jmp .l\nendm\nspin\nspin", 4),
+        [0x800, 0, 0x800, 2]
+    );
+    assert_eq!(
+        words(
+            "macro load n\nloadn r0, #n\nendm\nload #5\nload end\nend:",
+            4
+        ),
+        [0xe000, 5, 0xe000, 4]
+    );
+    assert!(matches!(
+        load("macro at n\nn: halt\nendm\nat #1"),
+        Err(SourceError::InvalidSyntax { line: 2, .. })
+    ));
+    assert!(matches!(
+        load("macro pair a, b\nendm\npair #1"),
+        Err(SourceError::MacroArguments {
+            expected: 2,
+            found: 1,
+            ..
+        })
+    ));
+    assert!(matches!(
+        load("macro spin\nspin\nendm\nspin"),
+        Err(SourceError::ExpansionDepth { .. })
+    ));
+}
+
+#[test]
+fn numeric_labels() {
+    assert_eq!(
+        words("1: jmp 1f\n1: jmp 1b\n2: word 2b, 1f\n1: halt", 6),
+        [0x800, 2, 0x800, 2, 4, 6]
+    );
+    assert!(matches!(
+        parse("jmp 1b"),
+        Err(ReduceError::UnknownIdentifier(pair)) if pair.as_str() == "1b"
+    ));
+    assert!(matches!(
+        parse("jmp 1f\n2: halt"),
+        Err(ReduceError::UnknownIdentifier(pair)) if pair.as_str() == "1f"
+    ));
+}
+
+#[test]
+fn scoped_labels() {
+    let source = "f:\n.a: word .a, ..b\n..b: word f.a.b\ng: word f.a, .x\n.x: halt";
+
+    assert_eq!(words(source, 6), [0, 2, 2, 0, 5, 0x3c00]);
+    assert_eq!(words("f:\n.a:\ng:\n.a: word f.a, g.a, .a", 3), [0, 0, 0]);
+    assert!(matches!(
+        parse(".a: halt"),
+        Err(ReduceError::InvalidLabel(_))
+    ));
+    assert!(matches!(
+        parse("f:\n..a: halt"),
+        Err(ReduceError::InvalidLabel(_))
+    ));
+    assert!(matches!(
+        parse("f:\n.a: halt\n.a: halt"),
+        Err(ReduceError::LabelRedeclaration { .. })
+    ));
+}
+
+#[test]
+fn namespaces() {
+    let source = "print: halt\nnamespace video\nexport print\nprint: word print, ::print\nnamespace inner\nprint: word print, video::print\nendnamespace\nendnamespace\nword video::print";
+
+    assert_eq!(words(source, 6), [0x3c00, 1, 0, 3, 1, 1]);
+    assert!(matches!(
+        parse("namespace a\nx: halt\nendnamespace\nword a::x"),
+        Err(ReduceError::NotExported(pair)) if pair.as_str() == "a::x"
+    ));
+    assert!(matches!(
+        parse("namespace a\nhalt"),
+        Err(ReduceError::UnbalancedNamespace(_))
+    ));
+    assert!(matches!(
+        parse("endnamespace"),
+        Err(ReduceError::UnbalancedNamespace(_))
+    ));
+}
+
+#[test]
+fn text_entries() {
+    let assembly = assemble_from_buf("word #1, #2", SYNTAX).unwrap();
+
+    assert_eq!(assembly.binary()[..2], [1, 2]);
+
+    let files = provider(&[("src/lib.asm", "word BOARD"), ("inc/board.asm", "word #3")]);
+    let options = Options {
+        include_paths: vec!["inc".into()],
+        defines: HashMap::from([("BOARD".to_owned(), 2)]),
+        ..Default::default()
+    };
+    let input = "include \"lib.asm\"\ninclude \"board.asm\"";
+    let assembly = assemble_from_buf_with(&files, "src/main.asm", input, SYNTAX, &options);
+
+    assert_eq!(assembly.unwrap().binary()[..2], [2, 3]);
+
+    let err = assemble_from_buf_with(&files, "src/main.asm", "word nope", SYNTAX, &options);
+
+    assert!(err.err().unwrap().starts_with("src/main.asm:1:6: "));
+}
+
+#[test]
+fn imported_symbols() {
+    let rom = provider(&[("main.asm", "reset: halt\nsection data, #0x30\nd: word #1")]);
+    let rom = assemble_from(&rom, "main.asm", "syntax.toml", &Options::default()).unwrap();
+    let options = Options {
+        symbols: crate::parse_symbols(&rom.symbols()).unwrap(),
+        ..Default::default()
+    };
+    let files = provider(&[(
+        "main.asm",
+        "org #0x10\nword reset, d, __data_start\nsection data, #0x40\nword #2",
+    )]);
+
+    assert_eq!(
+        parse_files(&files, &options).unwrap()[0x10..0x13],
+        [0, 0x30, 0x40]
+    );
+
+    let files = provider(&[("main.asm", "org #0x10\nreset: halt")]);
+
+    assert!(matches!(
+        parse_files(&files, &options),
+        Err(ReduceError::ImportConflict(_))
+    ));
+    assert!(crate::parse_symbols("a 0x10").is_err());
+}
+
+#[test]
+fn dependency_chains() {
+    // each size is the one of the next block, only known once the last block is
+    let chain = |length: usize| {
+        let mut source = String::new();
+
+        for block in 0..length {
+            source.push_str(&format!(
+                "org #{}\nfill s{} - #{}, #{block}\ns{block}:\n",
+                block * 4,
+                block + 1,
+                (block + 1) * 4
+            ));
+        }
+
+        source + &format!("org #{}\nword #1, #2\ns{length}:", length * 4)
+    };
+
+    assert_eq!(words(&chain(2), 10), [0, 0, 0, 0, 1, 1, 0, 0, 1, 2]);
+    assert_eq!(
+        parse(&chain(5000)).unwrap()[4 * 4999..4 * 5000 + 2],
+        [4999, 4999, 0, 0, 1, 2]
+    );
+
+    // sizes after a provisional address depend on where it ends
+    assert_eq!(
+        words("fill x - #0x20, #1\nalign #4\ny: word y\norg #0x25\nx:", 9)[5..],
+        [0, 0, 0, 8]
+    );
+
+    // numeric labels with the same name are told apart
+    let source = "fill x - #50, #0\n1: halt\nfill 1b - x + #50, #0\n1: halt\norg #100\nx: word 1b";
+
+    assert_eq!(parse(source).unwrap()[49..52], [0, 0x3c00, 0x3c00]);
+    assert_eq!(parse(source).unwrap()[100], 51);
+}
+
+#[test]
+fn cyclic_definitions() {
+    assert!(matches!(
+        parse("fill end, #0\nend: halt"),
+        Err(ReduceError::CyclicDefinition { cycle, .. }) if cycle == ["end", "end"]
+    ));
+    assert!(matches!(
+        parse("halt\nreserve __code_end"),
+        Err(ReduceError::CyclicDefinition { statement, cycle })
+            if statement.as_str() == "reserve" && cycle == ["__code_end", "__code_end"]
+    ));
+    assert!(matches!(
+        parse("fill 1f, #0\n1: halt"),
+        Err(ReduceError::CyclicDefinition { cycle, .. }) if cycle == ["1", "1"]
+    ));
+    assert!(matches!(
+        parse("fill b, #0\na: halt\nfill a, #0\nb: halt"),
+        Err(ReduceError::CyclicDefinition { cycle, .. }) if cycle == ["b", "a", "b"]
+    ));
+    assert_eq!(
+        words("a: halt\nb: fill b - a, #7\nc: fill c - a, #0\nword c", 5),
+        [0x3c00, 7, 0, 0, 2]
+    );
+}